@@ -5,15 +5,70 @@ use anyhow::anyhow;
 use clap::Parser;
 use enum_dispatch::enum_dispatch;
 
-use crate::{CmdExecutor, cli::verify_file, process_base64_decode, process_base64_encode};
+use crate::{
+    CmdExecutor, cli::verify_file, process_base64_decode, process_base64_decode_text,
+    process_base64_encode, process_data_uri_decode, process_data_uri_encode,
+};
 
 #[derive(Parser, Debug)]
 #[enum_dispatch(CmdExecutor)]
 pub enum Base64Command {
-    #[command(about = "Decode base64 to output")]
+    #[command(about = "Decode base64, base32, base58, base85 or hex to output")]
     Decode(DecodeOpts),
-    #[command(about = "Encode input to base64")]
+    #[command(about = "Encode input to base64, base32, base58, base85 or hex")]
     Encode(EncodeOpts),
+    #[command(
+        subcommand,
+        about = "Wrap a file as a data: URI or extract its payload"
+    )]
+    DataUri(DataUriCommand),
+}
+
+#[derive(Parser, Debug)]
+#[enum_dispatch(CmdExecutor)]
+pub enum DataUriCommand {
+    #[command(about = "Decode a data: URI and write out its payload")]
+    Decode(DataUriDecodeOpts),
+    #[command(about = "Encode input as data:<mime>;base64,...")]
+    Encode(DataUriEncodeOpts),
+}
+
+#[derive(Debug, Parser)]
+pub struct DataUriDecodeOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+
+    /// Payload output file, default stdout
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+
+    /// Fail unless the data URI declares this mime type
+    #[arg(long)]
+    pub expect: Option<String>,
+}
+
+impl CmdExecutor for DataUriDecodeOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        process_data_uri_decode(&self.input, &self.output, self.expect.as_deref())
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct DataUriEncodeOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+
+    /// Mime type, sniffed from magic bytes or the file extension by default
+    #[arg(long)]
+    pub mime: Option<String>,
+}
+
+impl CmdExecutor for DataUriEncodeOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let uri = process_data_uri_encode(&self.input, self.mime.as_deref())?;
+        println!("{}", uri);
+        Ok(())
+    }
 }
 
 #[derive(Debug, Parser)]
@@ -21,18 +76,34 @@ pub struct DecodeOpts {
     #[arg(short, long, value_parser = verify_file, default_value = "-")]
     pub input: String,
 
-    /// Format support standard, url_safe
-    #[arg(long, value_parser = verify_base64_format, default_value = "standard")]
-    pub format: Base64Format,
+    /// Format support standard, standard_no_pad, url_safe, url_safe_no_pad, base32,
+    /// base32_no_pad, base58, ascii85, z85, hex, auto detects the base64 alphabet and padding
+    #[arg(long, value_parser = verify_codec_format, default_value = "standard")]
+    pub format: CodecFormat,
+
+    /// Ignore padding and letter case instead of requiring the canonical form,
+    /// whitespace is always ignored
+    #[arg(long)]
+    pub lenient: bool,
+
+    /// Decoded bytes output file, default stdout
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+
+    /// Print decoded bytes as lossy utf8 text, this reads the whole input
+    #[arg(long, conflicts_with = "output")]
+    pub text: bool,
 }
 
 impl CmdExecutor for DecodeOpts {
     async fn execute(self) -> anyhow::Result<()> {
-        let result = process_base64_decode(&self.input, self.format)?;
+        if self.text {
+            let text = process_base64_decode_text(&self.input, self.format, self.lenient)?;
+            print!("{}", text);
+            return Ok(());
+        }
 
-        // TODO: decode output maybe not string, but for this case assume it is string
-        print!("{}", String::from_utf8_lossy(&result));
-        Ok(())
+        process_base64_decode(&self.input, &self.output, self.format, self.lenient)
     }
 }
 
@@ -41,53 +112,93 @@ pub struct EncodeOpts {
     #[arg(short, long, value_parser = verify_file, default_value = "-")]
     pub input: String,
 
-    /// Format support standard, url_safe
-    #[arg(long, value_parser = verify_base64_format, default_value = "standard")]
-    pub format: Base64Format,
+    /// Format support standard, standard_no_pad, url_safe, url_safe_no_pad, base32,
+    /// base32_no_pad, base58, ascii85, z85, hex
+    #[arg(long, value_parser = verify_encode_format, default_value = "standard")]
+    pub format: CodecFormat,
+
+    /// Encoded output file, default stdout
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+
+    /// Wrap lines at this many characters, 76 for MIME, 0 disables wrapping
+    #[arg(long, default_value_t = 0)]
+    pub wrap: usize,
 }
 
 impl CmdExecutor for EncodeOpts {
     async fn execute(self) -> anyhow::Result<()> {
-        process_base64_encode(&self.input, self.format)
+        process_base64_encode(&self.input, &self.output, self.format, self.wrap)
     }
 }
 
 #[derive(Debug, Clone, Copy)]
-pub enum Base64Format {
+pub enum CodecFormat {
     Standard,
+    StandardNoPad,
     UrlSafe,
+    UrlSafeNoPad,
+    Base32,
+    Base32NoPad,
+    Base58,
+    Ascii85,
+    Z85,
+    Hex,
+    Auto,
 }
 
-impl FromStr for Base64Format {
+impl FromStr for CodecFormat {
     type Err = anyhow::Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
-            "standard" => Ok(Base64Format::Standard),
-            "url_safe" => Ok(Base64Format::UrlSafe),
-            _ => Err(anyhow!("Invalid base64 format")),
+            "standard" => Ok(CodecFormat::Standard),
+            "standard_no_pad" => Ok(CodecFormat::StandardNoPad),
+            "url_safe" => Ok(CodecFormat::UrlSafe),
+            "url_safe_no_pad" => Ok(CodecFormat::UrlSafeNoPad),
+            "base32" => Ok(CodecFormat::Base32),
+            "base32_no_pad" => Ok(CodecFormat::Base32NoPad),
+            "base58" => Ok(CodecFormat::Base58),
+            "ascii85" => Ok(CodecFormat::Ascii85),
+            "z85" => Ok(CodecFormat::Z85),
+            "hex" => Ok(CodecFormat::Hex),
+            "auto" => Ok(CodecFormat::Auto),
+            _ => Err(anyhow!("Invalid codec format")),
         }
     }
 }
 
-impl fmt::Display for Base64Format {
+impl fmt::Display for CodecFormat {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Base64Format::Standard => write!(f, "standard"),
-            Base64Format::UrlSafe => write!(f, "url_safe"),
-        }
+        f.write_str((*self).into())
     }
 }
 
-impl From<Base64Format> for &str {
-    fn from(value: Base64Format) -> Self {
+impl From<CodecFormat> for &'static str {
+    fn from(value: CodecFormat) -> Self {
         match value {
-            Base64Format::Standard => "standard",
-            Base64Format::UrlSafe => "url_safe",
+            CodecFormat::Standard => "standard",
+            CodecFormat::StandardNoPad => "standard_no_pad",
+            CodecFormat::UrlSafe => "url_safe",
+            CodecFormat::UrlSafeNoPad => "url_safe_no_pad",
+            CodecFormat::Base32 => "base32",
+            CodecFormat::Base32NoPad => "base32_no_pad",
+            CodecFormat::Base58 => "base58",
+            CodecFormat::Ascii85 => "ascii85",
+            CodecFormat::Z85 => "z85",
+            CodecFormat::Hex => "hex",
+            CodecFormat::Auto => "auto",
         }
     }
 }
 
-fn verify_base64_format(format: &str) -> Result<Base64Format, String> {
+fn verify_codec_format(format: &str) -> Result<CodecFormat, String> {
     format.parse().map_err(|e: anyhow::Error| e.to_string())
 }
+
+fn verify_encode_format(format: &str) -> Result<CodecFormat, String> {
+    match verify_codec_format(format)? {
+        CodecFormat::Auto => Err("Auto format is only supported for decoding".into()),
+        format => Ok(format),
+    }
+}
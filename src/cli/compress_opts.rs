@@ -0,0 +1,123 @@
+use core::fmt;
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use clap::Parser;
+
+use crate::{CmdExecutor, cli::verify_file, process_compress, process_decompress};
+
+#[derive(Debug, Parser)]
+pub struct CompressOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+
+    /// Compressed output file, default stdout
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+
+    /// Format support gzip, zlib, deflate, zstd, brotli
+    #[arg(long, value_parser = verify_compress_format, default_value = "gzip")]
+    pub format: CompressFormat,
+
+    /// Level, 0-9 for gzip/zlib/deflate (default 6), 1-22 for zstd (default 3),
+    /// 0-11 for brotli (default 6)
+    #[arg(short, long)]
+    pub level: Option<i32>,
+
+    /// Base64 encode the compressed output, e.g. to embed it in an env var
+    #[arg(long)]
+    pub base64: bool,
+}
+
+impl CmdExecutor for CompressOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        process_compress(
+            &self.input,
+            &self.output,
+            self.format,
+            self.level,
+            self.base64,
+        )
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct DecompressOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+
+    /// Decompressed output file, default stdout
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+
+    /// Format support auto, gzip, zlib, deflate, zstd, brotli. Auto detects gzip, zlib
+    /// and zstd from their magic bytes, raw or base64 encoded
+    #[arg(long, value_parser = verify_decompress_format, default_value = "auto")]
+    pub format: CompressFormat,
+
+    /// Input is base64 encoded, only needed with an explicit format
+    #[arg(long)]
+    pub base64: bool,
+}
+
+impl CmdExecutor for DecompressOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        process_decompress(&self.input, &self.output, self.format, self.base64)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum CompressFormat {
+    Gzip,
+    Zlib,
+    Deflate,
+    Zstd,
+    Brotli,
+    Auto,
+}
+
+impl FromStr for CompressFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "gzip" => Ok(CompressFormat::Gzip),
+            "zlib" => Ok(CompressFormat::Zlib),
+            "deflate" => Ok(CompressFormat::Deflate),
+            "zstd" => Ok(CompressFormat::Zstd),
+            "brotli" => Ok(CompressFormat::Brotli),
+            "auto" => Ok(CompressFormat::Auto),
+            _ => Err(anyhow!("Invalid compress format")),
+        }
+    }
+}
+
+impl fmt::Display for CompressFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str((*self).into())
+    }
+}
+
+impl From<CompressFormat> for &'static str {
+    fn from(value: CompressFormat) -> Self {
+        match value {
+            CompressFormat::Gzip => "gzip",
+            CompressFormat::Zlib => "zlib",
+            CompressFormat::Deflate => "deflate",
+            CompressFormat::Zstd => "zstd",
+            CompressFormat::Brotli => "brotli",
+            CompressFormat::Auto => "auto",
+        }
+    }
+}
+
+fn verify_decompress_format(format: &str) -> Result<CompressFormat, String> {
+    format.parse().map_err(|e: anyhow::Error| e.to_string())
+}
+
+fn verify_compress_format(format: &str) -> Result<CompressFormat, String> {
+    match verify_decompress_format(format)? {
+        CompressFormat::Auto => Err("Auto format is only supported for decompressing".into()),
+        format => Ok(format),
+    }
+}
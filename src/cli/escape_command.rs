@@ -0,0 +1,117 @@
+use core::fmt;
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use clap::Parser;
+use enum_dispatch::enum_dispatch;
+
+use crate::{CmdExecutor, cli::verify_file, process_escape, process_unescape};
+
+#[derive(Parser, Debug)]
+#[enum_dispatch(CmdExecutor)]
+pub enum EscapeCommand {
+    #[command(about = "Unescape url, form, html or json/rust string input")]
+    Decode(EscapeDecodeOpts),
+    #[command(about = "Escape input for a url, form, html or json/rust string")]
+    Encode(EscapeEncodeOpts),
+}
+
+#[derive(Debug, Parser)]
+pub struct EscapeDecodeOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+
+    /// Format support url, url_path, url_query, url_fragment, url_userinfo, form, html,
+    /// json, rust
+    #[arg(long, value_parser = verify_escape_format, default_value = "url")]
+    pub format: EscapeFormat,
+
+    /// Output file, default stdout
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+}
+
+impl CmdExecutor for EscapeDecodeOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        process_unescape(&self.input, &self.output, self.format)
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct EscapeEncodeOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+
+    /// Format support url, url_path, url_query, url_fragment, url_userinfo, form, html,
+    /// json, rust
+    #[arg(long, value_parser = verify_escape_format, default_value = "url")]
+    pub format: EscapeFormat,
+
+    /// Output file, default stdout
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+}
+
+impl CmdExecutor for EscapeEncodeOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        process_escape(&self.input, &self.output, self.format)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum EscapeFormat {
+    Url,
+    UrlPath,
+    UrlQuery,
+    UrlFragment,
+    UrlUserinfo,
+    Form,
+    Html,
+    Json,
+    Rust,
+}
+
+impl FromStr for EscapeFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "url" => Ok(EscapeFormat::Url),
+            "url_path" => Ok(EscapeFormat::UrlPath),
+            "url_query" => Ok(EscapeFormat::UrlQuery),
+            "url_fragment" => Ok(EscapeFormat::UrlFragment),
+            "url_userinfo" => Ok(EscapeFormat::UrlUserinfo),
+            "form" => Ok(EscapeFormat::Form),
+            "html" => Ok(EscapeFormat::Html),
+            "json" => Ok(EscapeFormat::Json),
+            "rust" => Ok(EscapeFormat::Rust),
+            _ => Err(anyhow!("Invalid escape format")),
+        }
+    }
+}
+
+impl fmt::Display for EscapeFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str((*self).into())
+    }
+}
+
+impl From<EscapeFormat> for &'static str {
+    fn from(value: EscapeFormat) -> Self {
+        match value {
+            EscapeFormat::Url => "url",
+            EscapeFormat::UrlPath => "url_path",
+            EscapeFormat::UrlQuery => "url_query",
+            EscapeFormat::UrlFragment => "url_fragment",
+            EscapeFormat::UrlUserinfo => "url_userinfo",
+            EscapeFormat::Form => "form",
+            EscapeFormat::Html => "html",
+            EscapeFormat::Json => "json",
+            EscapeFormat::Rust => "rust",
+        }
+    }
+}
+
+fn verify_escape_format(format: &str) -> Result<EscapeFormat, String> {
+    format.parse().map_err(|e: anyhow::Error| e.to_string())
+}
@@ -1,9 +1,16 @@
-use clap::Parser;
+use core::fmt;
+use std::str::FromStr;
 
-use crate::{CmdExecutor, check_password_strength, process_gen_pass};
+use clap::{Args, Parser};
+use serde::Deserialize;
 
-#[derive(Debug, Parser)]
-pub struct GenPassOpts {
+use crate::{
+    CmdExecutor, PasswordPolicy, check_password_strength, cli::verify_file,
+    process_gen_pass_with_policy,
+};
+
+#[derive(Debug, Args)]
+pub struct CharsetOpts {
     #[arg(short, long, default_value_t = 16)]
     pub length: usize,
 
@@ -28,16 +35,103 @@ pub struct GenPassOpts {
     pub symbol: bool,
 }
 
+#[derive(Debug, Parser)]
+pub struct GenPassOpts {
+    #[command(flatten)]
+    pub charset: CharsetOpts,
+
+    /// Password policy file in toml or yaml
+    #[arg(long, value_parser = verify_file)]
+    pub policy: Option<String>,
+
+    /// Minimum zxcvbn score 0-4, overrides the policy file
+    #[arg(long, value_parser = clap::value_parser!(u8).range(0..=4))]
+    pub min_score: Option<u8>,
+
+    /// Minimum estimated entropy bits, overrides the policy file
+    #[arg(long)]
+    pub min_entropy: Option<f64>,
+
+    /// Required character class: number, lower, upper, symbol
+    #[arg(long = "require", value_parser = verify_char_class)]
+    pub require: Vec<CharClass>,
+
+    /// Substring the password must not contain
+    #[arg(long = "ban")]
+    pub ban: Vec<String>,
+
+    /// Reject passwords found in this local Pwned Passwords SHA-1 dump
+    #[arg(long, value_parser = verify_file)]
+    pub pwned_db: Option<String>,
+}
+
 impl CmdExecutor for GenPassOpts {
     async fn execute(self) -> anyhow::Result<()> {
-        let password = process_gen_pass(
-            self.length,
-            self.number,
-            self.lower,
-            self.upper,
-            self.symbol,
+        let mut policy = match &self.policy {
+            Some(path) => PasswordPolicy::load(path)?,
+            None => PasswordPolicy::default(),
+        };
+        if self.min_score.is_some() {
+            policy.min_score = self.min_score;
+        }
+        if self.min_entropy.is_some() {
+            policy.min_entropy_bits = self.min_entropy;
+        }
+        policy.required_classes.extend(self.require);
+        policy.banned_substrings.extend(self.ban);
+        if self.pwned_db.is_some() {
+            policy.pwned_db = self.pwned_db;
+        }
+
+        let charset = self.charset;
+        let password = process_gen_pass_with_policy(
+            charset.length,
+            charset.number,
+            charset.lower,
+            charset.upper,
+            charset.symbol,
+            &policy,
         )?;
+        println!("{}", password);
         check_password_strength(&password);
         Ok(())
     }
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CharClass {
+    Number,
+    Lower,
+    Upper,
+    Symbol,
+}
+
+impl FromStr for CharClass {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "number" => Ok(CharClass::Number),
+            "lower" => Ok(CharClass::Lower),
+            "upper" => Ok(CharClass::Upper),
+            "symbol" => Ok(CharClass::Symbol),
+            _ => Err(anyhow::anyhow!("Invalid character class")),
+        }
+    }
+}
+
+impl fmt::Display for CharClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CharClass::Number => write!(f, "number"),
+            CharClass::Lower => write!(f, "lower"),
+            CharClass::Upper => write!(f, "upper"),
+            CharClass::Symbol => write!(f, "symbol"),
+        }
+    }
+}
+
+fn verify_char_class(class: &str) -> Result<CharClass, String> {
+    class.parse().map_err(|e: anyhow::Error| e.to_string())
+}
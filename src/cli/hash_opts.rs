@@ -0,0 +1,114 @@
+use core::fmt;
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use clap::Parser;
+
+use crate::{CmdExecutor, cli::verify_file, process_hash, process_hash_check};
+
+#[derive(Debug, Parser)]
+pub struct HashOpts {
+    /// Files to hash, or checksum lists with --check, default stdin
+    #[arg(value_parser = verify_file, default_value = "-")]
+    pub files: Vec<String>,
+
+    /// Algorithm support blake3, sha256, sha512, sha3-256. With --check it is only
+    /// used for lines without a BSD style tag
+    #[arg(short, long, value_parser = verify_hash_algorithm, default_value = "sha256")]
+    pub algorithm: HashAlgorithm,
+
+    /// Write BSD style lines, e.g. `SHA256 (file) = digest`
+    #[arg(long, conflicts_with = "check")]
+    pub tag: bool,
+
+    /// Read checksum lists and verify the files in them
+    #[arg(short, long)]
+    pub check: bool,
+
+    /// Don't print OK for each verified file
+    #[arg(short, long, requires = "check")]
+    pub quiet: bool,
+}
+
+impl CmdExecutor for HashOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        match self.check {
+            true => process_hash_check(&self.files, self.algorithm, self.quiet),
+            false => process_hash(&self.files, self.algorithm, self.tag),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HashAlgorithm {
+    Blake3,
+    Sha256,
+    Sha512,
+    Sha3_256,
+}
+
+impl HashAlgorithm {
+    /// Name used in BSD style checksum lines
+    pub fn tag(self) -> &'static str {
+        match self {
+            HashAlgorithm::Blake3 => "BLAKE3",
+            HashAlgorithm::Sha256 => "SHA256",
+            HashAlgorithm::Sha512 => "SHA512",
+            HashAlgorithm::Sha3_256 => "SHA3-256",
+        }
+    }
+
+    pub fn from_tag(tag: &str) -> Option<Self> {
+        [
+            HashAlgorithm::Blake3,
+            HashAlgorithm::Sha256,
+            HashAlgorithm::Sha512,
+            HashAlgorithm::Sha3_256,
+        ]
+        .into_iter()
+        .find(|algorithm| algorithm.tag() == tag)
+    }
+
+    /// Length of the hex digest
+    pub fn hex_len(self) -> usize {
+        match self {
+            HashAlgorithm::Sha512 => 128,
+            _ => 64,
+        }
+    }
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "blake3" => Ok(HashAlgorithm::Blake3),
+            "sha256" => Ok(HashAlgorithm::Sha256),
+            "sha512" => Ok(HashAlgorithm::Sha512),
+            "sha3-256" => Ok(HashAlgorithm::Sha3_256),
+            _ => Err(anyhow!("Invalid hash algorithm")),
+        }
+    }
+}
+
+impl fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str((*self).into())
+    }
+}
+
+impl From<HashAlgorithm> for &'static str {
+    fn from(value: HashAlgorithm) -> Self {
+        match value {
+            HashAlgorithm::Blake3 => "blake3",
+            HashAlgorithm::Sha256 => "sha256",
+            HashAlgorithm::Sha512 => "sha512",
+            HashAlgorithm::Sha3_256 => "sha3-256",
+        }
+    }
+}
+
+fn verify_hash_algorithm(algorithm: &str) -> Result<HashAlgorithm, String> {
+    algorithm.parse().map_err(|e: anyhow::Error| e.to_string())
+}
@@ -0,0 +1,72 @@
+use clap::Parser;
+use enum_dispatch::enum_dispatch;
+
+use crate::{CmdExecutor, cli::verify_file, process_hex_dump, process_hex_undump};
+
+#[derive(Parser, Debug)]
+#[enum_dispatch(CmdExecutor)]
+pub enum HexCommand {
+    #[command(about = "Show offset, hex and ascii columns like xxd")]
+    Dump(HexDumpOpts),
+    #[command(about = "Turn an xxd style or plain hex dump back into bytes")]
+    Undump(HexUndumpOpts),
+}
+
+#[derive(Debug, Parser)]
+pub struct HexDumpOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+
+    /// Bytes per line
+    #[arg(short, long, default_value_t = 16)]
+    pub cols: usize,
+
+    /// Bytes per group, 0 puts the whole line in one group
+    #[arg(short, long, default_value_t = 2)]
+    pub group: usize,
+
+    /// Skip this many bytes of input, offsets stay absolute
+    #[arg(short, long, default_value_t = 0)]
+    pub seek: u64,
+
+    /// Stop after this many bytes
+    #[arg(short, long)]
+    pub length: Option<u64>,
+
+    /// Color bytes by class: printable, whitespace, control, 0x00 and 0xff
+    #[arg(long)]
+    pub color: bool,
+}
+
+impl CmdExecutor for HexDumpOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        process_hex_dump(
+            &self.input,
+            self.cols,
+            self.group,
+            self.seek,
+            self.length,
+            self.color,
+        )
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct HexUndumpOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+
+    /// Output file, default stdout
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+
+    /// Added to every offset in the dump, use the negative dump seek to drop leading zeros
+    #[arg(short, long, default_value_t = 0, allow_negative_numbers = true)]
+    pub seek: i64,
+}
+
+impl CmdExecutor for HexUndumpOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        process_hex_undump(&self.input, &self.output, self.seek)
+    }
+}
@@ -61,8 +61,8 @@ impl CmdExecutor for JwtSignOpts {
         let custom_claims = self.json.unwrap_or(serde_json::Value::Null);
         let mut claims = Claims::with_custom_claims(custom_claims, self.exp.into());
 
-        if self.sub.is_some() {
-            claims = claims.with_subject(self.sub.unwrap());
+        if let Some(sub) = self.sub {
+            claims = claims.with_subject(sub);
         }
         if !self.aud.is_empty() {
             claims = claims.with_audiences(HashSet::from_iter(self.aud));
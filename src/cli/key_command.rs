@@ -0,0 +1,119 @@
+use core::fmt;
+use std::str::FromStr;
+
+use clap::Parser;
+use enum_dispatch::enum_dispatch;
+
+use crate::{CmdExecutor, cli::verify_file, process_key_convert, process_key_info};
+
+#[derive(Parser, Debug)]
+#[enum_dispatch(CmdExecutor)]
+pub enum KeyCommand {
+    #[command(about = "Convert an ed25519 key between raw, hex, base64, pem, der and openssh")]
+    Convert(KeyConvertOpts),
+
+    #[command(about = "Show the type and fingerprint of a key, or check a secret/public pair")]
+    Info(KeyInfoOpts),
+}
+
+#[derive(Debug, Parser)]
+pub struct KeyConvertOpts {
+    /// Key in any supported format, detected automatically
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+
+    /// Output file, default stdout
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+
+    /// Support raw, hex, base64, pem (PKCS#8 or SPKI), der, openssh
+    #[arg(long, value_parser = verify_key_format, default_value = "pem")]
+    pub to: KeyFormat,
+
+    /// Write the public key, derived from the input when it is a secret key
+    #[arg(long)]
+    pub public: bool,
+
+    /// Read a raw, hex or base64 input as a public key, it is taken as a secret key otherwise
+    #[arg(long)]
+    pub public_input: bool,
+}
+
+impl CmdExecutor for KeyConvertOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        process_key_convert(
+            &self.input,
+            &self.output,
+            self.to,
+            self.public,
+            self.public_input,
+        )
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct KeyInfoOpts {
+    /// Key file, raw 32 byte keys are told apart by name, e.g. ed25519.sk or blake3.txt
+    #[arg(value_parser = verify_file, default_value = "-")]
+    pub key: String,
+
+    /// Another key file that should be the same key or its public half, fails if not
+    #[arg(long, value_parser = verify_file)]
+    pub pair: Option<String>,
+}
+
+impl CmdExecutor for KeyInfoOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        process_key_info(&self.key, self.pair.as_deref())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeyFormat {
+    Raw,
+    Hex,
+    Base64,
+    /// PKCS#8 for secret keys, SPKI for public keys
+    Pem,
+    Der,
+    OpenSsh,
+}
+
+impl FromStr for KeyFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "raw" => Ok(KeyFormat::Raw),
+            "hex" => Ok(KeyFormat::Hex),
+            "base64" => Ok(KeyFormat::Base64),
+            "pem" => Ok(KeyFormat::Pem),
+            "der" => Ok(KeyFormat::Der),
+            "openssh" => Ok(KeyFormat::OpenSsh),
+            _ => Err(anyhow::anyhow!("Invalid key format")),
+        }
+    }
+}
+
+impl fmt::Display for KeyFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str((*self).into())
+    }
+}
+
+impl From<KeyFormat> for &'static str {
+    fn from(value: KeyFormat) -> Self {
+        match value {
+            KeyFormat::Raw => "raw",
+            KeyFormat::Hex => "hex",
+            KeyFormat::Base64 => "base64",
+            KeyFormat::Pem => "pem",
+            KeyFormat::Der => "der",
+            KeyFormat::OpenSsh => "openssh",
+        }
+    }
+}
+
+fn verify_key_format(format: &str) -> Result<KeyFormat, String> {
+    format.parse().map_err(|e: anyhow::Error| e.to_string())
+}
@@ -3,19 +3,35 @@ use std::path::{Path, PathBuf};
 use clap::Parser;
 
 mod base64_command;
+mod compress_opts;
 mod csv_opts;
+mod escape_command;
 mod gen_pass_opts;
+mod hash_opts;
+mod hex_command;
 mod http_command;
 mod jwt_command;
+mod key_command;
+mod otp_command;
+mod password_command;
 mod text_command;
+mod token_opts;
 
 pub use base64_command::*;
+pub use compress_opts::*;
 pub use csv_opts::*;
 use enum_dispatch::enum_dispatch;
+pub use escape_command::*;
 pub use gen_pass_opts::*;
+pub use hash_opts::*;
+pub use hex_command::*;
 pub use http_command::*;
 pub use jwt_command::*;
+pub use key_command::*;
+pub use otp_command::*;
+pub use password_command::*;
 pub use text_command::*;
+pub use token_opts::*;
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
@@ -33,9 +49,61 @@ pub enum Commands {
     #[command(name = "genpass", about = "Generate a random password")]
     GenPass(GenPassOpts),
 
-    #[command(subcommand, about = "Encode/Decode base64")]
+    #[command(subcommand, about = "Totp/Hotp secret, uri, code and verify")]
+    Otp(OtpCommand),
+
+    #[command(
+        name = "token",
+        about = "Generate random api keys, session secrets and ids"
+    )]
+    Token(TokenOpts),
+
+    #[command(
+        name = "encode",
+        about = "Encode input to base64, base32, base58, base85 or hex"
+    )]
+    Encode(EncodeOpts),
+
+    #[command(
+        name = "decode",
+        about = "Decode base64, base32, base58, base85 or hex to output"
+    )]
+    Decode(DecodeOpts),
+
+    #[command(
+        subcommand,
+        visible_alias = "codec",
+        about = "Encode/Decode base64, base32, base58, base85 and hex, same as encode/decode"
+    )]
     Base64(Base64Command),
 
+    #[command(
+        subcommand,
+        about = "Escape/Unescape url components, form data, html and json/rust strings"
+    )]
+    Escape(EscapeCommand),
+
+    #[command(subcommand, about = "Hex dump and reverse hex dump, xxd compatible")]
+    Hex(HexCommand),
+
+    #[command(
+        name = "compress",
+        about = "Compress with gzip, zlib, deflate, zstd or brotli"
+    )]
+    Compress(CompressOpts),
+
+    #[command(
+        name = "decompress",
+        about = "Decompress gzip, zlib, deflate, zstd or brotli, detecting the format"
+    )]
+    Decompress(DecompressOpts),
+
+    #[command(
+        name = "hash",
+        about = "Checksum files with blake3, sha256, sha512 or sha3-256, or --check a list"
+    )]
+    Hash(HashOpts),
+
     #[command(subcommand, about = "Text encrypt/decrypt/sign/verify")]
     Text(TextCommand),
 
@@ -44,6 +112,12 @@ pub enum Commands {
 
     #[command(subcommand, about = "Jwt sign and verify")]
     Jwt(JwtCommand),
+
+    #[command(subcommand, about = "Convert and inspect keys")]
+    Key(KeyCommand),
+
+    #[command(subcommand, about = "Password strength audit")]
+    Password(PasswordCommand),
 }
 
 pub fn verify_file(filename: &str) -> Result<String, &'static str> {
@@ -65,7 +139,12 @@ pub fn verify_path(path: &str) -> Result<PathBuf, &'static str> {
 
 #[cfg(test)]
 mod test {
-    use crate::cli::verify_file;
+    use clap::Parser;
+
+    use crate::{
+        CmdExecutor,
+        cli::{Cli, Commands, verify_file},
+    };
 
     #[test]
     fn test_verify_input_file() {
@@ -73,4 +152,69 @@ mod test {
         assert_eq!(verify_file("Cargo.toml"), Ok("Cargo.toml".into()));
         assert_eq!(verify_file("File not exist"), Err("Input file not exist"));
     }
+
+    #[tokio::test]
+    async fn test_decode_output_and_text() {
+        let dir = std::env::temp_dir();
+        let input = dir.join(format!("rcli-decode-{}.b64", std::process::id()));
+        let output = dir.join(format!("rcli-decode-{}.bin", std::process::id()));
+        std::fs::write(&input, "/wD+").unwrap();
+        let (input, output) = (input.to_str().unwrap(), output.to_str().unwrap());
+
+        let cli = Cli::try_parse_from(["rcli", "decode", "-i", input, "--output", output]).unwrap();
+        cli.command.execute().await.unwrap();
+        assert_eq!(std::fs::read(output).unwrap(), [0xff, 0x00, 0xfe]);
+
+        let cli = Cli::try_parse_from(["rcli", "decode", "-i", input, "--text"]).unwrap();
+        assert!(matches!(cli.command, Commands::Decode(ref opts) if opts.text));
+        cli.command.execute().await.unwrap();
+
+        // Text goes to stdout only, it can't be combined with an output file
+        let args = ["rcli", "decode", "-i", input, "--text", "--output", output];
+        assert!(Cli::try_parse_from(args).is_err());
+
+        std::fs::remove_file(input).unwrap();
+        std::fs::remove_file(output).unwrap();
+    }
+
+    #[test]
+    fn test_verify_signature_file_needs_a_key_source() {
+        let verify = |args: &[&str]| {
+            let base = ["rcli", "text", "verify", "-i", "Cargo.toml"];
+            Cli::try_parse_from(base.iter().chain(args)).map(|_| ())
+        };
+        assert!(verify(&["--sig", "Cargo.toml"]).is_err());
+        assert!(verify(&["--sig", "Cargo.toml", "--key", "fixtures/ed25519.pk"]).is_ok());
+        assert!(verify(&["--sig", "Cargo.toml", "--key-dir", "fixtures"]).is_ok());
+        assert!(verify(&["--key-dir", "fixtures", "--sign", "AA"]).is_err());
+        let both = [
+            "--sig",
+            "Cargo.toml",
+            "--key-dir",
+            "fixtures",
+            "-k",
+            "Cargo.toml",
+        ];
+        assert!(verify(&both).is_err());
+    }
+
+    #[test]
+    fn test_encrypt_algorithm_needs_a_container() {
+        let encrypt = |args: &[&str]| {
+            let base = ["rcli", "text", "encrypt", "-i", "Cargo.toml", "-o", "-"];
+            Cli::try_parse_from(base.iter().chain(args)).map(|_| ())
+        };
+        let recipient = "age1ql3z7hjy54pw3hyww5ayyfg7zqgvc7w3j2elw8zmrj2kg5sfn9aqmcac8p";
+        assert!(encrypt(&["--recipient", recipient]).is_ok());
+        assert!(encrypt(&["--recipient", recipient, "--algorithm", "aes256gcm"]).is_err());
+        assert!(encrypt(&["--password", "--algorithm", "aes256gcm"]).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_genpass_rejects_length_below_classes() {
+        let cli = Cli::try_parse_from(["rcli", "genpass", "-l", "2"]).unwrap();
+        assert!(cli.command.execute().await.is_err());
+        let cli = Cli::try_parse_from(["rcli", "genpass", "-l", "2", "--no-upper", "--no-symbol"]);
+        cli.unwrap().command.execute().await.unwrap();
+    }
 }
@@ -0,0 +1,182 @@
+use core::fmt;
+use std::str::FromStr;
+
+use clap::{Args, Parser};
+use enum_dispatch::enum_dispatch;
+
+use crate::{
+    CmdExecutor, MAX_OTP_WINDOW, process_otp_code, process_otp_secret, process_otp_uri,
+    process_otp_verify,
+};
+
+#[derive(Parser, Debug)]
+#[enum_dispatch(CmdExecutor)]
+pub enum OtpCommand {
+    #[command(about = "Generate a random base32 otp secret")]
+    Secret(OtpSecretOpts),
+
+    #[command(about = "Print otpauth:// provisioning uri")]
+    Uri(OtpUriOpts),
+
+    #[command(about = "Compute the current totp code, or hotp code with --counter")]
+    Code(OtpCodeOpts),
+
+    #[command(about = "Verify an otp code within a drift window")]
+    Verify(OtpVerifyOpts),
+}
+
+#[derive(Debug, Args)]
+pub struct OtpParams {
+    /// Base32 encoded shared secret
+    #[arg(long)]
+    pub secret: String,
+
+    /// Use hotp with this counter instead of time based totp
+    #[arg(long)]
+    pub counter: Option<u64>,
+
+    #[arg(long, default_value_t = 6, value_parser = clap::value_parser!(u32).range(6..=10))]
+    pub digits: u32,
+
+    /// Totp time step in seconds
+    #[arg(long, default_value_t = 30, value_parser = clap::value_parser!(u64).range(1..))]
+    pub period: u64,
+
+    /// Support sha1, sha256, sha512
+    #[arg(long, value_parser = verify_otp_algorithm, default_value = "sha1")]
+    pub algorithm: OtpAlgorithm,
+}
+
+#[derive(Debug, Parser)]
+pub struct OtpSecretOpts {
+    /// Secret length in bytes, rfc 4226 recommends 20
+    #[arg(long, default_value_t = 20)]
+    pub bytes: usize,
+}
+
+impl CmdExecutor for OtpSecretOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        println!("{}", process_otp_secret(self.bytes)?);
+        Ok(())
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct OtpUriOpts {
+    /// Account name such as user email
+    #[arg(long)]
+    pub account: String,
+
+    #[arg(long)]
+    pub issuer: Option<String>,
+
+    #[command(flatten)]
+    pub params: OtpParams,
+}
+
+impl CmdExecutor for OtpUriOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let params = self.params;
+        let uri = process_otp_uri(
+            &params.secret,
+            &self.account,
+            self.issuer.as_deref(),
+            params.counter,
+            params.digits,
+            params.period,
+            params.algorithm,
+        )?;
+        println!("{}", uri);
+        Ok(())
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct OtpCodeOpts {
+    #[command(flatten)]
+    pub params: OtpParams,
+}
+
+impl CmdExecutor for OtpCodeOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let params = self.params;
+        let code = process_otp_code(
+            &params.secret,
+            params.counter,
+            params.digits,
+            params.period,
+            params.algorithm,
+        )?;
+        println!("{}", code);
+        Ok(())
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct OtpVerifyOpts {
+    #[arg(long)]
+    pub code: String,
+
+    /// Accepted steps of drift, totp checks both ways and hotp looks ahead, at most 100
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u64).range(0..=MAX_OTP_WINDOW))]
+    pub window: u64,
+
+    #[command(flatten)]
+    pub params: OtpParams,
+}
+
+impl CmdExecutor for OtpVerifyOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let params = self.params;
+        let matched = process_otp_verify(
+            &params.secret,
+            &self.code,
+            params.counter,
+            self.window,
+            params.digits,
+            params.period,
+            params.algorithm,
+        )?;
+        match matched {
+            Some(offset) => {
+                println!("Valid code, drift {} step(s)", offset);
+                Ok(())
+            }
+            None => Err(anyhow::anyhow!("Invalid otp code")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum OtpAlgorithm {
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+impl FromStr for OtpAlgorithm {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sha1" => Ok(OtpAlgorithm::Sha1),
+            "sha256" => Ok(OtpAlgorithm::Sha256),
+            "sha512" => Ok(OtpAlgorithm::Sha512),
+            _ => Err(anyhow::anyhow!("Invalid otp algorithm")),
+        }
+    }
+}
+
+impl fmt::Display for OtpAlgorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OtpAlgorithm::Sha1 => write!(f, "sha1"),
+            OtpAlgorithm::Sha256 => write!(f, "sha256"),
+            OtpAlgorithm::Sha512 => write!(f, "sha512"),
+        }
+    }
+}
+
+fn verify_otp_algorithm(algorithm: &str) -> Result<OtpAlgorithm, String> {
+    algorithm.parse().map_err(|e: anyhow::Error| e.to_string())
+}
@@ -0,0 +1,217 @@
+use core::fmt;
+use std::str::FromStr;
+
+use clap::Parser;
+use enum_dispatch::enum_dispatch;
+
+use crate::{
+    CharsetOpts, CmdExecutor, HashParams, cli::verify_file, process_derive_pass,
+    process_password_audit, process_password_hash, process_password_verify, read_secret,
+};
+
+#[derive(Parser, Debug)]
+#[enum_dispatch(CmdExecutor)]
+pub enum PasswordCommand {
+    #[command(about = "Audit password strength from stdin, file or csv column")]
+    Audit(AuditOpts),
+
+    #[command(about = "Derive a site password from a master secret")]
+    Derive(DeriveOpts),
+
+    #[command(about = "Hash password with argon2id, scrypt or bcrypt")]
+    Hash(PasswordHashOpts),
+
+    #[command(about = "Verify password against a PHC or bcrypt hash")]
+    Verify(PasswordVerifyOpts),
+}
+
+#[derive(Debug, Parser)]
+pub struct AuditOpts {
+    /// Passwords file, one password per line
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+
+    /// Read passwords from this csv column instead of lines
+    #[arg(short, long)]
+    pub column: Option<String>,
+
+    #[arg(short, long, default_value_t = ',')]
+    pub delimiter: char,
+
+    /// User context words such as username or company name
+    #[arg(short = 'w', long = "user-input")]
+    pub user_inputs: Vec<String>,
+
+    /// Fail when any password scores lower than this, 0-4
+    #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u8).range(0..=4))]
+    pub min_score: u8,
+
+    /// Local Pwned Passwords SHA-1 dump sorted by hash
+    #[arg(long, value_parser = verify_file)]
+    pub pwned_db: Option<String>,
+
+    /// Print passwords in the report
+    #[arg(long)]
+    pub show: bool,
+}
+
+impl CmdExecutor for AuditOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        process_password_audit(
+            &self.input,
+            self.column.as_deref(),
+            self.delimiter,
+            &self.user_inputs,
+            self.min_score,
+            self.pwned_db.as_deref(),
+            self.show,
+        )
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct DeriveOpts {
+    #[arg(long)]
+    pub site: String,
+
+    #[arg(long, default_value = "")]
+    pub login: String,
+
+    /// Bump to rotate the password without changing the master secret
+    #[arg(short, long, default_value_t = 1)]
+    pub counter: u32,
+
+    /// Read the master secret from this env var instead of prompting
+    #[arg(long)]
+    pub master_env: Option<String>,
+
+    #[command(flatten)]
+    pub charset: CharsetOpts,
+}
+
+impl CmdExecutor for DeriveOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let master = read_secret("Master secret: ", self.master_env.as_deref())?;
+        let charset = self.charset;
+        let password = process_derive_pass(
+            &master,
+            &self.site,
+            &self.login,
+            self.counter,
+            charset.length,
+            charset.number,
+            charset.lower,
+            charset.upper,
+            charset.symbol,
+        )?;
+        println!("{}", password);
+        Ok(())
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct PasswordHashOpts {
+    /// Password input, pipe from `rcli genpass` or type in stdin
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+
+    /// Support argon2id, scrypt, bcrypt
+    #[arg(long, value_parser = verify_hash_algorithm, default_value = "argon2id")]
+    pub algorithm: PasswordHashAlgorithm,
+
+    /// Argon2 memory cost in KiB
+    #[arg(long, default_value_t = HashParams::default().memory)]
+    pub memory: u32,
+
+    /// Argon2 iterations
+    #[arg(long, default_value_t = HashParams::default().iterations)]
+    pub iterations: u32,
+
+    /// Argon2 lanes or scrypt parallelism
+    #[arg(long, default_value_t = HashParams::default().parallelism)]
+    pub parallelism: u32,
+
+    /// Scrypt log2 of the cpu/memory cost
+    #[arg(long, default_value_t = HashParams::default().log_n)]
+    pub log_n: u8,
+
+    /// Scrypt block size
+    #[arg(long, default_value_t = HashParams::default().block_size)]
+    pub block_size: u32,
+
+    /// Bcrypt cost, 4-31
+    #[arg(long, default_value_t = HashParams::default().cost)]
+    pub cost: u32,
+}
+
+impl CmdExecutor for PasswordHashOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let params = HashParams {
+            memory: self.memory,
+            iterations: self.iterations,
+            parallelism: self.parallelism,
+            log_n: self.log_n,
+            block_size: self.block_size,
+            cost: self.cost,
+        };
+        let hash = process_password_hash(&self.input, self.algorithm, &params)?;
+        println!("{}", hash);
+        Ok(())
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct PasswordVerifyOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+
+    /// PHC string or bcrypt hash
+    #[arg(long)]
+    pub hash: String,
+}
+
+impl CmdExecutor for PasswordVerifyOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        match process_password_verify(&self.input, &self.hash)? {
+            true => {
+                println!("Password matches");
+                Ok(())
+            }
+            false => Err(anyhow::anyhow!("Password does not match")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum PasswordHashAlgorithm {
+    Argon2id,
+    Scrypt,
+    Bcrypt,
+}
+
+impl FromStr for PasswordHashAlgorithm {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "argon2id" => Ok(PasswordHashAlgorithm::Argon2id),
+            "scrypt" => Ok(PasswordHashAlgorithm::Scrypt),
+            "bcrypt" => Ok(PasswordHashAlgorithm::Bcrypt),
+            _ => Err(anyhow::anyhow!("Invalid password hash algorithm")),
+        }
+    }
+}
+
+impl fmt::Display for PasswordHashAlgorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PasswordHashAlgorithm::Argon2id => write!(f, "argon2id"),
+            PasswordHashAlgorithm::Scrypt => write!(f, "scrypt"),
+            PasswordHashAlgorithm::Bcrypt => write!(f, "bcrypt"),
+        }
+    }
+}
+
+fn verify_hash_algorithm(algorithm: &str) -> Result<PasswordHashAlgorithm, String> {
+    algorithm.parse().map_err(|e: anyhow::Error| e.to_string())
+}
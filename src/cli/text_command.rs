@@ -1,14 +1,16 @@
 use core::fmt;
 use std::{fs, path::PathBuf, str::FromStr};
 
-use clap::Parser;
+use clap::{Args, Parser};
 use enum_dispatch::enum_dispatch;
 
 use crate::{
-    CmdExecutor,
+    CmdExecutor, HashParams, PasswordHashAlgorithm,
     cli::{verify_file, verify_path},
-    process_key_generate, process_text_decrypt, process_text_encrypt, process_text_sign,
-    process_text_verify,
+    process_key_generate, process_text_decrypt, process_text_decrypt_legacy, process_text_encrypt,
+    process_text_encrypt_password, process_text_encrypt_recipients, process_text_sign,
+    process_text_sign_detached, process_text_sign_stream, process_text_verify,
+    process_text_verify_detached, process_text_verify_stream, read_secret, write_buffer_to_output,
 };
 
 #[derive(Parser, Debug)]
@@ -23,13 +25,24 @@ pub enum TextCommand {
     #[command(about = "Generate sign/verify key or encrypt/decrypt key")]
     Generate(GenerateOpts),
 
-    #[command(about = "Encrypt text")]
+    #[command(about = "Encrypt text", after_long_help = ENCRYPT_FORMAT)]
     Encrypt(EncryptOpts),
 
     #[command(about = "Decrypt encrypted text")]
     Decrypt(DecryptOpts),
 }
 
+const ENCRYPT_FORMAT: &str = "\
+Output format, unless --recipient writes an age v1 file:
+  header   \"rcli-enc\", version 1, aead id, key source (1 key file, 2 password followed by
+           the kdf, its params and a 16 byte salt), associated data length (BE16) and bytes
+  prefix   random STREAM nonce prefix, 7 bytes, 19 for xchacha20poly1305
+  segments 64 KiB of plaintext each followed by a 16 byte tag, the last one may be shorter
+           or empty. The nonce is the prefix, a BE32 segment counter and a last segment
+           flag byte, the header is the associated data of every segment
+This is not a single nonce||ciphertext||tag message, other tools need a STREAM (BE32)
+decryptor to read it.";
+
 #[derive(Debug, Parser)]
 pub struct SignTextOpts {
     #[arg(short, long, value_parser = verify_file, default_value = "-")]
@@ -39,14 +52,51 @@ pub struct SignTextOpts {
     #[arg(short, long, value_parser = verify_file)]
     pub key: String,
 
-    /// Support blake3, ed25519, chacha20poly1305
+    /// Support blake3, ed25519, ed25519ph, chacha20poly1305
     #[arg(long, value_parser = verify_format, default_value = "blake3")]
     pub format: TextSignFormat,
+
+    /// Read the input in chunks instead of loading it all, ed25519ph and --sig always stream
+    #[arg(long)]
+    pub stream: bool,
+
+    /// Context string bound into an ed25519ph signature, stored in the --sig file
+    #[arg(long)]
+    pub context: Option<String>,
+
+    /// Write a detached signature file with the algorithm, key id, time, file name, context
+    /// and size
+    #[arg(long)]
+    pub sig: Option<String>,
+
+    /// Detached signature file format, support json, armor
+    #[arg(long, value_parser = verify_signature_format, default_value = "json", requires = "sig")]
+    pub sig_format: SignatureFormat,
 }
 
 impl CmdExecutor for SignTextOpts {
     async fn execute(self) -> anyhow::Result<()> {
-        let sign = process_text_sign(&self.input, &self.key, self.format)?;
+        if let Some(sig) = self.sig {
+            let envelope = process_text_sign_detached(
+                &self.input,
+                &self.key,
+                self.format,
+                self.context.as_deref(),
+                self.sig_format,
+            )?;
+            fs::write(sig, envelope)?;
+            return Ok(());
+        }
+
+        let sign = match (self.stream, self.format) {
+            (true, _) | (_, TextSignFormat::ED25519ph) => process_text_sign_stream(
+                &self.input,
+                &self.key,
+                self.format,
+                self.context.as_deref(),
+            )?,
+            _ => process_text_sign(&self.input, &self.key, self.format)?,
+        };
         print!("{}", sign);
 
         Ok(())
@@ -58,21 +108,59 @@ pub struct VerifyTextOpts {
     #[arg(short, long, value_parser = verify_file, default_value = "-")]
     pub input: String,
 
-    /// Public key for verify signature
-    #[arg(short, long, value_parser = verify_file)]
-    pub key: String,
+    /// Public key for verify signature, or find it in --key-dir with --sig
+    #[arg(short, long, value_parser = verify_file, required_unless_present = "key_dir")]
+    pub key: Option<String>,
 
-    #[arg(short, long)]
-    pub sign: String,
+    #[arg(
+        short,
+        long,
+        required_unless_present = "sig",
+        conflicts_with_all = ["sig", "key_dir"]
+    )]
+    pub sign: Option<String>,
 
-    /// Support blake3, ed25519, chacha20poly1305
+    /// Support blake3, ed25519, ed25519ph, chacha20poly1305
     #[arg(long, value_parser = verify_format, default_value = "blake3")]
     pub format: TextSignFormat,
+
+    /// Read the input in chunks instead of loading it all, ed25519ph and --sig always stream
+    #[arg(long)]
+    pub stream: bool,
+
+    /// Context string the ed25519ph signature was made with, checked against the --sig file
+    #[arg(long)]
+    pub context: Option<String>,
+
+    /// Detached signature file, the algorithm and key come from it
+    #[arg(long, value_parser = verify_file)]
+    pub sig: Option<String>,
+
+    /// Where to look for the key matching the signature's key id instead of --key
+    #[arg(long, requires = "sig", conflicts_with = "key")]
+    pub key_dir: Option<String>,
 }
 
 impl CmdExecutor for VerifyTextOpts {
     async fn execute(self) -> anyhow::Result<()> {
-        let result = process_text_verify(&self.input, &self.key, self.format, self.sign)?;
+        let result = match (self.sig, self.sign, self.key) {
+            (Some(sig), _, key) => {
+                let key_dir = self.key_dir.as_deref();
+                let context = self.context.as_deref();
+                process_text_verify_detached(&self.input, &sig, key.as_deref(), key_dir, context)?
+            }
+            (None, Some(sign), Some(key)) => match (self.stream, self.format) {
+                (true, _) | (_, TextSignFormat::ED25519ph) => process_text_verify_stream(
+                    &self.input,
+                    &key,
+                    self.format,
+                    self.context.as_deref(),
+                    sign,
+                )?,
+                _ => process_text_verify(&self.input, &key, self.format, sign)?,
+            },
+            _ => unreachable!("clap requires --sign and --key without --sig"),
+        };
         println!("{}", result);
         Ok(())
     }
@@ -80,7 +168,8 @@ impl CmdExecutor for VerifyTextOpts {
 
 #[derive(Debug, Parser)]
 pub struct GenerateOpts {
-    /// Support blake3, ed25519, jwt_ed25519, chacha20poly1305
+    /// Support blake3, ed25519, ed25519ph, jwt_ed25519, chacha20poly1305, xchacha20poly1305,
+    /// aes256gcm, aes256gcmsiv, x25519
     #[arg(long, value_parser = verify_format, default_value = "blake3")]
     pub format: TextSignFormat,
 
@@ -97,7 +186,7 @@ impl CmdExecutor for GenerateOpts {
                 let path = self.output.join("blake3.txt");
                 fs::write(&path, &result[0])?;
             }
-            TextSignFormat::ED25519 => {
+            TextSignFormat::ED25519 | TextSignFormat::ED25519ph => {
                 assert_eq!(result.len(), 2, "Generate ED25519 key failed");
                 let pk_path = self.output.join("ed25519.pk");
                 let sk_path = self.output.join("ed25519.sk");
@@ -111,11 +200,21 @@ impl CmdExecutor for GenerateOpts {
                 fs::write(sk_path, &result[0])?;
                 fs::write(pk_path, &result[1])?;
             }
-            TextSignFormat::ChaCha20Poly1305 => {
-                assert_eq!(result.len(), 1, "Generate ChaCha20Poly1305 key failed");
-                let path = self.output.join("chacha20poly1305.txt");
+            TextSignFormat::ChaCha20Poly1305
+            | TextSignFormat::XChaCha20Poly1305
+            | TextSignFormat::Aes256Gcm
+            | TextSignFormat::Aes256GcmSiv => {
+                assert_eq!(result.len(), 1, "Generate {} key failed", self.format);
+                let path = self.output.join(format!("{}.txt", self.format));
                 fs::write(&path, &result[0])?;
             }
+            TextSignFormat::X25519 => {
+                assert_eq!(result.len(), 2, "Generate X25519 key failed");
+                let pk_path = self.output.join("x25519.pk");
+                let sk_path = self.output.join("x25519.sk");
+                fs::write(sk_path, &result[0])?;
+                fs::write(pk_path, &result[1])?;
+            }
         };
 
         Ok(())
@@ -132,15 +231,114 @@ pub struct EncryptOpts {
     pub output: String,
 
     // Private key
-    #[arg(short, long, value_parser = verify_file)]
-    pub key: String,
+    #[arg(short, long, value_parser = verify_file, required_unless_present_any = ["password", "password_env", "recipient"])]
+    pub key: Option<String>,
+
+    /// Support chacha20poly1305, xchacha20poly1305, aes256gcm, aes256gcmsiv. Age files
+    /// always use chacha20poly1305
+    #[arg(long, value_parser = verify_aead, default_value = "chacha20poly1305")]
+    pub algorithm: AeadAlgorithm,
+
+    /// Associated data stored in clear in the header and authenticated, e.g. a file name
+    #[arg(long)]
+    pub aad: Option<String>,
+
+    /// X25519 `age1...` public key or a file of them, repeatable. Writes an age file any
+    /// one recipient's x25519.sk can decrypt
+    #[arg(short, long, conflicts_with_all = ["key", "password", "password_env", "aad", "algorithm"])]
+    pub recipient: Vec<String>,
+
+    /// Encrypt with a password prompted on the terminal instead of a key file
+    #[arg(long, conflicts_with = "key")]
+    pub password: bool,
+
+    /// Read the password from this env var instead of prompting
+    #[arg(long, conflicts_with = "key")]
+    pub password_env: Option<String>,
+
+    #[command(flatten)]
+    pub kdf: KdfOpts,
 }
 
 impl CmdExecutor for EncryptOpts {
     async fn execute(self) -> anyhow::Result<()> {
-        let ciphertext = process_text_encrypt(&self.input, &self.key)?;
-        fs::write(self.output, ciphertext)?;
-        Ok(())
+        if !self.recipient.is_empty() {
+            return process_text_encrypt_recipients(&self.input, &self.output, &self.recipient);
+        }
+        let Some(key) = self.key else {
+            let password = match self.password_env {
+                Some(env) => read_secret("Password: ", Some(&env))?,
+                None => {
+                    let password = read_secret("Password: ", None)?;
+                    if read_secret("Confirm password: ", None)? != password {
+                        return Err(anyhow::anyhow!("Passwords do not match"));
+                    }
+                    password
+                }
+            };
+            return process_text_encrypt_password(
+                &self.input,
+                &self.output,
+                &password,
+                self.kdf.kdf,
+                self.kdf.params(),
+                self.algorithm,
+                self.aad.as_deref(),
+            );
+        };
+
+        process_text_encrypt(
+            &self.input,
+            &self.output,
+            &key,
+            self.algorithm,
+            self.aad.as_deref(),
+        )
+    }
+}
+
+/// Key derivation for password based encryption, stored in the ciphertext header
+#[derive(Debug, Args)]
+pub struct KdfOpts {
+    /// Support argon2id, scrypt
+    #[arg(long, value_parser = verify_kdf, default_value = "argon2id")]
+    pub kdf: PasswordHashAlgorithm,
+
+    /// Argon2 memory cost in KiB
+    #[arg(long, default_value_t = HashParams::default().memory)]
+    pub memory: u32,
+
+    /// Argon2 iterations
+    #[arg(long, default_value_t = HashParams::default().iterations)]
+    pub iterations: u32,
+
+    /// Argon2 lanes or scrypt parallelism
+    #[arg(long, default_value_t = HashParams::default().parallelism)]
+    pub parallelism: u32,
+
+    /// Scrypt log2 of the cpu/memory cost
+    #[arg(long, default_value_t = HashParams::default().log_n)]
+    pub log_n: u8,
+}
+
+impl KdfOpts {
+    pub fn params(&self) -> HashParams {
+        HashParams {
+            memory: self.memory,
+            iterations: self.iterations,
+            parallelism: self.parallelism,
+            log_n: self.log_n,
+            ..HashParams::default()
+        }
+    }
+}
+
+fn verify_kdf(kdf: &str) -> Result<PasswordHashAlgorithm, String> {
+    match kdf.parse().map_err(|e: anyhow::Error| e.to_string())? {
+        PasswordHashAlgorithm::Bcrypt => {
+            Err("Bcrypt can't derive keys, use argon2id or scrypt".into())
+        }
+        kdf => Ok(kdf),
     }
 }
 
@@ -149,16 +347,42 @@ pub struct DecryptOpts {
     #[arg(short, long, value_parser = verify_file, default_value = "-")]
     pub input: String,
 
-    // Private key
-    #[arg(short, long, value_parser = verify_file)]
-    pub key: String,
+    // Private key, or the x25519.sk identity file for age input, not needed for password
+    // encrypted input
+    #[arg(short, long, value_parser = verify_file, required_if_eq("legacy", "true"))]
+    pub key: Option<String>,
+
+    /// Read the password from this env var instead of prompting
+    #[arg(long, conflicts_with = "legacy")]
+    pub password_env: Option<String>,
+
+    /// Fail unless the ciphertext carries exactly this associated data
+    #[arg(long, conflicts_with = "legacy")]
+    pub aad: Option<String>,
+
+    /// Input is the raw `nonce || ciphertext` layout from before the container format
+    #[arg(long)]
+    pub legacy: bool,
+
+    /// Plaintext output file, default stdout
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
 }
 
 impl CmdExecutor for DecryptOpts {
     async fn execute(self) -> anyhow::Result<()> {
-        let plaintext = process_text_decrypt(&self.input, &self.key)?;
-        print!("{}", String::from_utf8(plaintext)?);
-        Ok(())
+        if self.legacy {
+            let key = self.key.expect("clap requires --key with --legacy");
+            let plaintext = process_text_decrypt_legacy(&self.input, &key)?;
+            return write_buffer_to_output(&self.output, &plaintext);
+        }
+        process_text_decrypt(
+            &self.input,
+            &self.output,
+            self.key.as_deref(),
+            || read_secret("Password: ", self.password_env.as_deref()),
+            self.aad.as_deref(),
+        )
     }
 }
 
@@ -166,8 +390,13 @@ impl CmdExecutor for DecryptOpts {
 pub enum TextSignFormat {
     Blake3,
     ED25519,
+    ED25519ph,
     ChaCha20Poly1305,
+    XChaCha20Poly1305,
+    Aes256Gcm,
+    Aes256GcmSiv,
     JWTED25519,
+    X25519,
 }
 
 impl FromStr for TextSignFormat {
@@ -177,8 +406,13 @@ impl FromStr for TextSignFormat {
         match s {
             "blake3" => Ok(TextSignFormat::Blake3),
             "ed25519" => Ok(TextSignFormat::ED25519),
+            "ed25519ph" => Ok(TextSignFormat::ED25519ph),
             "jwted25519" => Ok(TextSignFormat::JWTED25519),
             "chacha20poly1305" => Ok(TextSignFormat::ChaCha20Poly1305),
+            "xchacha20poly1305" => Ok(TextSignFormat::XChaCha20Poly1305),
+            "aes256gcm" => Ok(TextSignFormat::Aes256Gcm),
+            "aes256gcmsiv" => Ok(TextSignFormat::Aes256GcmSiv),
+            "x25519" => Ok(TextSignFormat::X25519),
             _ => Err(anyhow::anyhow!("Invalid text sign format")),
         }
     }
@@ -189,8 +423,13 @@ impl fmt::Display for TextSignFormat {
         match self {
             TextSignFormat::Blake3 => write!(f, "blake3"),
             TextSignFormat::ED25519 => write!(f, "ed25519"),
+            TextSignFormat::ED25519ph => write!(f, "ed25519ph"),
             TextSignFormat::JWTED25519 => write!(f, "jwted25519"),
             TextSignFormat::ChaCha20Poly1305 => write!(f, "chacha20poly1305"),
+            TextSignFormat::XChaCha20Poly1305 => write!(f, "xchacha20poly1305"),
+            TextSignFormat::Aes256Gcm => write!(f, "aes256gcm"),
+            TextSignFormat::Aes256GcmSiv => write!(f, "aes256gcmsiv"),
+            TextSignFormat::X25519 => write!(f, "x25519"),
         }
     }
 }
@@ -200,8 +439,13 @@ impl From<TextSignFormat> for &'static str {
         match value {
             TextSignFormat::Blake3 => "blake3",
             TextSignFormat::ED25519 => "ed25519",
+            TextSignFormat::ED25519ph => "ed25519ph",
             TextSignFormat::JWTED25519 => "jwted25519",
             TextSignFormat::ChaCha20Poly1305 => "chacha20poly1305",
+            TextSignFormat::XChaCha20Poly1305 => "xchacha20poly1305",
+            TextSignFormat::Aes256Gcm => "aes256gcm",
+            TextSignFormat::Aes256GcmSiv => "aes256gcmsiv",
+            TextSignFormat::X25519 => "x25519",
         }
     }
 }
@@ -209,3 +453,80 @@ impl From<TextSignFormat> for &'static str {
 fn verify_format(format: &str) -> Result<TextSignFormat, String> {
     format.parse().map_err(|e: anyhow::Error| e.to_string())
 }
+
+/// Aead used for the body of `text encrypt` output, the key is 32 bytes for all of them
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AeadAlgorithm {
+    ChaCha20Poly1305,
+    /// 192-bit nonces, safe to pick at random for any number of files
+    XChaCha20Poly1305,
+    Aes256Gcm,
+    /// Nonce misuse resistant, a repeated nonce only reveals identical segments
+    Aes256GcmSiv,
+}
+
+impl FromStr for AeadAlgorithm {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "chacha20poly1305" => Ok(AeadAlgorithm::ChaCha20Poly1305),
+            "xchacha20poly1305" => Ok(AeadAlgorithm::XChaCha20Poly1305),
+            "aes256gcm" => Ok(AeadAlgorithm::Aes256Gcm),
+            "aes256gcmsiv" => Ok(AeadAlgorithm::Aes256GcmSiv),
+            _ => Err(anyhow::anyhow!("Invalid aead algorithm")),
+        }
+    }
+}
+
+impl fmt::Display for AeadAlgorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str((*self).into())
+    }
+}
+
+impl From<AeadAlgorithm> for &'static str {
+    fn from(value: AeadAlgorithm) -> Self {
+        match value {
+            AeadAlgorithm::ChaCha20Poly1305 => "chacha20poly1305",
+            AeadAlgorithm::XChaCha20Poly1305 => "xchacha20poly1305",
+            AeadAlgorithm::Aes256Gcm => "aes256gcm",
+            AeadAlgorithm::Aes256GcmSiv => "aes256gcmsiv",
+        }
+    }
+}
+
+fn verify_aead(algorithm: &str) -> Result<AeadAlgorithm, String> {
+    algorithm.parse().map_err(|e: anyhow::Error| e.to_string())
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum SignatureFormat {
+    Json,
+    Armor,
+}
+
+impl FromStr for SignatureFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(SignatureFormat::Json),
+            "armor" => Ok(SignatureFormat::Armor),
+            _ => Err(anyhow::anyhow!("Invalid signature format")),
+        }
+    }
+}
+
+impl fmt::Display for SignatureFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SignatureFormat::Json => write!(f, "json"),
+            SignatureFormat::Armor => write!(f, "armor"),
+        }
+    }
+}
+
+fn verify_signature_format(format: &str) -> Result<SignatureFormat, String> {
+    format.parse().map_err(|e: anyhow::Error| e.to_string())
+}
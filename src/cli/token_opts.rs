@@ -0,0 +1,105 @@
+use core::fmt;
+use std::str::FromStr;
+
+use clap::Parser;
+
+use crate::{CmdExecutor, process_token, verify_token_checksum};
+
+#[derive(Debug, Parser)]
+pub struct TokenOpts {
+    /// Random bytes for hex, base32, base64url and base58 tokens
+    #[arg(short, long, default_value_t = 32)]
+    pub bytes: usize,
+
+    /// Support hex, base32, base64url, base58, uuid4, uuid7, ulid
+    #[arg(short, long, value_parser = verify_token_format, default_value = "base64url")]
+    pub format: TokenFormat,
+
+    /// Prefix such as sk_live_
+    #[arg(short, long, default_value = "")]
+    pub prefix: String,
+
+    /// Append a blake3 checksum suffix
+    #[arg(long)]
+    pub checksum: bool,
+
+    /// Number of tokens to generate
+    #[arg(short = 'n', long, default_value_t = 1)]
+    pub count: usize,
+
+    /// Check the checksum suffix of an existing token instead of generating
+    #[arg(long)]
+    pub verify: Option<String>,
+}
+
+impl CmdExecutor for TokenOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        if let Some(token) = self.verify {
+            return match verify_token_checksum(&token) {
+                true => {
+                    println!("Checksum valid");
+                    Ok(())
+                }
+                false => Err(anyhow::anyhow!("Invalid token checksum")),
+            };
+        }
+
+        let tokens = process_token(
+            self.bytes,
+            self.format,
+            &self.prefix,
+            self.checksum,
+            self.count,
+        )?;
+        for token in tokens {
+            println!("{}", token);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum TokenFormat {
+    Hex,
+    Base32,
+    Base64Url,
+    Base58,
+    UuidV4,
+    UuidV7,
+    Ulid,
+}
+
+impl FromStr for TokenFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hex" => Ok(TokenFormat::Hex),
+            "base32" => Ok(TokenFormat::Base32),
+            "base64url" => Ok(TokenFormat::Base64Url),
+            "base58" => Ok(TokenFormat::Base58),
+            "uuid4" => Ok(TokenFormat::UuidV4),
+            "uuid7" => Ok(TokenFormat::UuidV7),
+            "ulid" => Ok(TokenFormat::Ulid),
+            _ => Err(anyhow::anyhow!("Invalid token format")),
+        }
+    }
+}
+
+impl fmt::Display for TokenFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TokenFormat::Hex => write!(f, "hex"),
+            TokenFormat::Base32 => write!(f, "base32"),
+            TokenFormat::Base64Url => write!(f, "base64url"),
+            TokenFormat::Base58 => write!(f, "base58"),
+            TokenFormat::UuidV4 => write!(f, "uuid4"),
+            TokenFormat::UuidV7 => write!(f, "uuid7"),
+            TokenFormat::Ulid => write!(f, "ulid"),
+        }
+    }
+}
+
+fn verify_token_format(format: &str) -> Result<TokenFormat, String> {
+    format.parse().map_err(|e: anyhow::Error| e.to_string())
+}
@@ -5,13 +5,26 @@ mod utils;
 pub use cli::*;
 use enum_dispatch::enum_dispatch;
 pub use process::process_csv;
-pub use process::process_gen_pass;
 pub use process::{
-    check_password_strength, process_base64_decode, process_base64_encode, process_http_serve,
-    process_key_generate, process_text_decrypt, process_text_encrypt, process_text_sign,
-    process_text_verify,
+    Ed25519Key, HashParams, MAX_OTP_WINDOW, PasswordPolicy, PwnedDb, SignatureEnvelope,
+    process_derive_pass, process_gen_pass, process_gen_pass_with_policy,
+};
+pub use process::{
+    check_password_strength, process_base64_decode, process_base64_decode_text,
+    process_base64_encode, process_compress, process_data_uri_decode, process_data_uri_encode,
+    process_decompress, process_escape, process_hash, process_hash_check, process_hex_dump,
+    process_hex_undump, process_http_serve, process_key_convert, process_key_generate,
+    process_key_info, process_otp_code, process_otp_secret, process_otp_uri, process_otp_verify,
+    process_password_audit, process_password_hash, process_password_verify, process_text_decrypt,
+    process_text_decrypt_legacy, process_text_encrypt, process_text_encrypt_password,
+    process_text_encrypt_recipients, process_text_sign, process_text_sign_detached,
+    process_text_sign_stream, process_text_verify, process_text_verify_detached,
+    process_text_verify_stream, process_token, process_unescape, verify_token_checksum,
+};
+pub use utils::{
+    open_input, open_input_trimmed, open_output, read_buffer_from_input, read_secret,
+    write_buffer_to_output, write_output_atomic,
 };
-pub use utils::read_buffer_from_input;
 
 #[allow(async_fn_in_trait)]
 #[enum_dispatch]
@@ -1,15 +1,51 @@
+mod process_aead;
+mod process_age;
 mod process_base64;
+mod process_compress;
 mod process_csv;
+mod process_data_uri;
+mod process_escape;
 mod process_gen_pass;
+mod process_hash;
+mod process_hex;
 mod process_http;
+mod process_key;
+mod process_otp;
+mod process_password;
+mod process_pwned;
+mod process_signature;
 mod process_text;
+mod process_token;
 
+pub use process_aead::{process_text_decrypt, process_text_encrypt, process_text_encrypt_password};
+pub use process_age::process_text_encrypt_recipients;
 pub use process_base64::*;
+pub use process_compress::{process_compress, process_decompress};
 pub use process_csv::process_csv;
-pub use process_gen_pass::{check_password_strength, process_gen_pass};
+pub use process_data_uri::{process_data_uri_decode, process_data_uri_encode};
+pub use process_escape::{process_escape, process_unescape};
+pub use process_gen_pass::{
+    PasswordPolicy, check_password_strength, process_derive_pass, process_gen_pass,
+    process_gen_pass_with_policy,
+};
+pub use process_hash::{process_hash, process_hash_check};
+pub use process_hex::{process_hex_dump, process_hex_undump};
+pub use process_otp::{
+    MAX_OTP_WINDOW, process_otp_code, process_otp_secret, process_otp_uri, process_otp_verify,
+};
+pub use process_password::{
+    HashParams, process_password_audit, process_password_hash, process_password_verify,
+};
 pub use process_text::{
-    process_key_generate, process_text_decrypt, process_text_encrypt, process_text_sign,
-    process_text_verify,
+    process_key_generate, process_text_decrypt_legacy, process_text_sign, process_text_sign_stream,
+    process_text_verify, process_text_verify_stream,
+};
+
+pub use process_signature::{
+    SignatureEnvelope, process_text_sign_detached, process_text_verify_detached,
 };
+pub use process_token::{process_token, verify_token_checksum};
 
 pub use process_http::process_http_serve;
+pub use process_key::{Ed25519Key, process_key_convert, process_key_info};
+pub use process_pwned::PwnedDb;
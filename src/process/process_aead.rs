@@ -0,0 +1,828 @@
+use std::{
+    io::{BufReader, Read, Write},
+    ops::Sub,
+};
+
+use aes_gcm::Aes256Gcm;
+use aes_gcm_siv::Aes256GcmSiv;
+use anyhow::{Context, Result};
+use argon2::{Algorithm, Argon2, Version};
+use chacha20poly1305::{
+    ChaCha20Poly1305, KeyInit, XChaCha20Poly1305,
+    aead::{
+        AeadInPlace, OsRng, Payload,
+        consts::U5,
+        generic_array::ArrayLength,
+        rand_core::RngCore,
+        stream::{DecryptorBE32, EncryptorBE32, Nonce, StreamBE32},
+    },
+};
+
+use super::process_age::{AGE_MAGIC, decrypt_with_identities, load_identities};
+use crate::{
+    AeadAlgorithm, HashParams, PasswordHashAlgorithm, open_input, open_input_trimmed, open_output,
+    read_buffer_from_input,
+    utils::{read_full, read_head},
+    write_output_atomic,
+};
+
+/// Plaintext bytes per segment, each segment grows by a 16 byte tag when encrypted
+pub const SEGMENT_LEN: usize = 64 * 1024;
+const TAG_LEN: usize = 16;
+const KEY_LEN: usize = 32;
+
+/// Container layout: magic, version, aead, key source, the source's info, associated
+/// data length and bytes, then the STREAM body. The whole header is the aad of every segment.
+pub const CONTAINER_MAGIC: &[u8; 8] = b"rcli-enc";
+const CONTAINER_VERSION: u8 = 1;
+const SOURCE_KEY: u8 = 1;
+const SOURCE_PASSWORD: u8 = 2;
+
+const SALT_LEN: usize = 16;
+const KDF_ARGON2ID: u8 = 1;
+const KDF_SCRYPT: u8 = 2;
+/// Upper bounds for params read from a file, so a crafted header can't exhaust memory or cpu
+const MAX_ARGON2_MEMORY: u32 = 4 * 1024 * 1024;
+const MAX_ARGON2_ITERATIONS: u32 = 64;
+const MAX_SCRYPT_LOG_N: u8 = 22;
+const MAX_SCRYPT_BLOCK_SIZE: u32 = 32;
+/// Scrypt needs 128 * block_size * 2^log_n bytes
+const MAX_SCRYPT_MEMORY: u64 = 4 * 1024 * 1024 * 1024;
+const MAX_PARALLELISM: u32 = 16;
+
+pub(crate) fn new_cipher<A: KeyInit>(key: &[u8]) -> Result<A> {
+    A::new_from_slice(key).map_err(|_| anyhow::anyhow!("Invalid key, expected {KEY_LEN} bytes"))
+}
+
+/// Id of the algorithm in the container header
+fn aead_id(algorithm: AeadAlgorithm) -> u8 {
+    match algorithm {
+        AeadAlgorithm::ChaCha20Poly1305 => 1,
+        AeadAlgorithm::XChaCha20Poly1305 => 2,
+        AeadAlgorithm::Aes256Gcm => 3,
+        AeadAlgorithm::Aes256GcmSiv => 4,
+    }
+}
+
+fn aead_from_id(id: u8) -> Result<AeadAlgorithm> {
+    [
+        AeadAlgorithm::ChaCha20Poly1305,
+        AeadAlgorithm::XChaCha20Poly1305,
+        AeadAlgorithm::Aes256Gcm,
+        AeadAlgorithm::Aes256GcmSiv,
+    ]
+    .into_iter()
+    .find(|algorithm| aead_id(*algorithm) == id)
+    .with_context(|| format!("Unknown aead algorithm {id}"))
+}
+
+/// Encrypt `reader` in fixed size segments, writing the nonce prefix then every
+/// segment. The last segment is sealed with the last flag set, even when empty.
+pub fn encrypt_stream(
+    reader: impl Read,
+    writer: impl Write,
+    algorithm: AeadAlgorithm,
+    key: &[u8],
+    aad: &[u8],
+) -> Result<()> {
+    match algorithm {
+        AeadAlgorithm::ChaCha20Poly1305 => seal::<ChaCha20Poly1305>(reader, writer, key, aad),
+        AeadAlgorithm::XChaCha20Poly1305 => seal::<XChaCha20Poly1305>(reader, writer, key, aad),
+        AeadAlgorithm::Aes256Gcm => seal::<Aes256Gcm>(reader, writer, key, aad),
+        AeadAlgorithm::Aes256GcmSiv => seal::<Aes256GcmSiv>(reader, writer, key, aad),
+    }
+}
+
+/// Decrypt what `encrypt_stream` wrote. Every segment is authenticated before it is
+/// written, a reordered, truncated or extended stream fails at the bad segment.
+pub fn decrypt_stream(
+    reader: impl Read,
+    writer: impl Write,
+    algorithm: AeadAlgorithm,
+    key: &[u8],
+    aad: &[u8],
+) -> Result<()> {
+    match algorithm {
+        AeadAlgorithm::ChaCha20Poly1305 => open::<ChaCha20Poly1305>(reader, writer, key, aad),
+        AeadAlgorithm::XChaCha20Poly1305 => open::<XChaCha20Poly1305>(reader, writer, key, aad),
+        AeadAlgorithm::Aes256Gcm => open::<Aes256Gcm>(reader, writer, key, aad),
+        AeadAlgorithm::Aes256GcmSiv => open::<Aes256GcmSiv>(reader, writer, key, aad),
+    }
+}
+
+// The STREAM nonce is a random prefix, a 4 byte big endian counter and a last flag
+// byte, so the prefix is 7 bytes for 96-bit nonces and 19 bytes for XChaCha20
+fn seal<A>(mut reader: impl Read, mut writer: impl Write, key: &[u8], aad: &[u8]) -> Result<()>
+where
+    A: AeadInPlace + KeyInit,
+    A::NonceSize: Sub<U5>,
+    <A::NonceSize as Sub<U5>>::Output: ArrayLength<u8>,
+{
+    let mut prefix = Nonce::<A, StreamBE32<A>>::default();
+    OsRng.fill_bytes(&mut prefix);
+    let mut encryptor = EncryptorBE32::from_aead(new_cipher::<A>(key)?, &prefix);
+    writer.write_all(&prefix)?;
+
+    let mut segment = vec![0u8; SEGMENT_LEN];
+    let mut next = vec![0u8; SEGMENT_LEN];
+    let mut len = read_full(&mut reader, &mut segment)?;
+    // A full segment is only the last one if nothing follows it, so read one ahead
+    loop {
+        let next_len = match len {
+            SEGMENT_LEN => read_full(&mut reader, &mut next)?,
+            _ => 0,
+        };
+        if next_len == 0 {
+            let sealed = encryptor
+                .encrypt_last(payload(&segment[..len], aad))
+                .map_err(|_| anyhow::anyhow!("Encrypt last segment failed"))?;
+            writer.write_all(&sealed)?;
+            break;
+        }
+        let sealed = encryptor
+            .encrypt_next(payload(&segment[..len], aad))
+            .map_err(|_| anyhow::anyhow!("Too many segments to encrypt"))?;
+        writer.write_all(&sealed)?;
+        (segment, next) = (next, segment);
+        len = next_len;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+fn open<A>(mut reader: impl Read, mut writer: impl Write, key: &[u8], aad: &[u8]) -> Result<()>
+where
+    A: AeadInPlace + KeyInit,
+    A::NonceSize: Sub<U5>,
+    <A::NonceSize as Sub<U5>>::Output: ArrayLength<u8>,
+{
+    let cipher = new_cipher::<A>(key)?;
+    let mut prefix = Nonce::<A, StreamBE32<A>>::default();
+    if read_full(&mut reader, &mut prefix)? < prefix.len() {
+        return Err(anyhow::anyhow!("Invalid ciphertext: too short"));
+    }
+    let mut decryptor = DecryptorBE32::from_aead(cipher, &prefix);
+
+    let mut segment = vec![0u8; SEGMENT_LEN + TAG_LEN];
+    let mut next = vec![0u8; SEGMENT_LEN + TAG_LEN];
+    let mut len = read_full(&mut reader, &mut segment)?;
+    for index in 0u64.. {
+        let next_len = match len {
+            len if len == SEGMENT_LEN + TAG_LEN => read_full(&mut reader, &mut next)?,
+            _ => 0,
+        };
+        if next_len == 0 {
+            let opened = decryptor
+                .decrypt_last(payload(&segment[..len], aad))
+                .map_err(|_| segment_error(index))?;
+            writer.write_all(&opened)?;
+            break;
+        }
+        let opened = decryptor
+            .decrypt_next(payload(&segment[..len], aad))
+            .map_err(|_| segment_error(index))?;
+        writer.write_all(&opened)?;
+        (segment, next) = (next, segment);
+        len = next_len;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+fn payload<'a>(msg: &'a [u8], aad: &'a [u8]) -> Payload<'a, 'a> {
+    Payload { msg, aad }
+}
+
+fn segment_error(index: u64) -> anyhow::Error {
+    anyhow::anyhow!(
+        "Decrypt segment {index} failed, the ciphertext is corrupted, truncated, reordered or the key is wrong"
+    )
+}
+
+/// Kdf and salt stored in front of a password encrypted stream
+#[derive(Debug, Clone, Copy)]
+pub struct PasswordHeader {
+    pub kdf: PasswordHashAlgorithm,
+    pub params: HashParams,
+    pub salt: [u8; SALT_LEN],
+}
+
+impl PasswordHeader {
+    pub fn generate(kdf: PasswordHashAlgorithm, params: HashParams) -> Result<Self> {
+        if let PasswordHashAlgorithm::Bcrypt = kdf {
+            return Err(anyhow::anyhow!(
+                "Bcrypt can't derive keys, use argon2id or scrypt"
+            ));
+        }
+        check_kdf_params(kdf, &params)?;
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        Ok(Self { kdf, params, salt })
+    }
+
+    pub fn write_to(&self, mut writer: impl Write) -> Result<()> {
+        match self.kdf {
+            PasswordHashAlgorithm::Argon2id => {
+                writer.write_all(&[KDF_ARGON2ID])?;
+                writer.write_all(&self.params.memory.to_be_bytes())?;
+                writer.write_all(&self.params.iterations.to_be_bytes())?;
+            }
+            PasswordHashAlgorithm::Scrypt => {
+                writer.write_all(&[KDF_SCRYPT, self.params.log_n])?;
+                writer.write_all(&self.params.block_size.to_be_bytes())?;
+            }
+            PasswordHashAlgorithm::Bcrypt => unreachable!("rejected by generate"),
+        }
+        writer.write_all(&self.params.parallelism.to_be_bytes())?;
+        writer.write_all(&self.salt)?;
+        Ok(())
+    }
+
+    pub fn read_from(mut reader: impl Read) -> Result<Self> {
+        let mut params = HashParams::default();
+        let kdf = match read_array::<1>(&mut reader)? {
+            [KDF_ARGON2ID] => {
+                params.memory = u32::from_be_bytes(read_array(&mut reader)?);
+                params.iterations = u32::from_be_bytes(read_array(&mut reader)?);
+                PasswordHashAlgorithm::Argon2id
+            }
+            [KDF_SCRYPT] => {
+                [params.log_n] = read_array(&mut reader)?;
+                params.block_size = u32::from_be_bytes(read_array(&mut reader)?);
+                PasswordHashAlgorithm::Scrypt
+            }
+            [kdf] => return Err(anyhow::anyhow!("Unknown kdf {kdf} in password header")),
+        };
+        params.parallelism = u32::from_be_bytes(read_array(&mut reader)?);
+        check_kdf_params(kdf, &params)?;
+        let salt = read_array(&mut reader)?;
+        Ok(Self { kdf, params, salt })
+    }
+
+    pub fn derive_key(&self, password: &[u8]) -> Result<[u8; KEY_LEN]> {
+        let mut key = [0u8; KEY_LEN];
+        match self.kdf {
+            PasswordHashAlgorithm::Argon2id => {
+                let params = argon2::Params::new(
+                    self.params.memory,
+                    self.params.iterations,
+                    self.params.parallelism,
+                    Some(KEY_LEN),
+                )
+                .map_err(|e| anyhow::anyhow!("Invalid argon2 params: {}", e))?;
+                Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+                    .hash_password_into(password, &self.salt, &mut key)
+                    .map_err(|e| anyhow::anyhow!("Argon2 derive key failed: {}", e))?;
+            }
+            PasswordHashAlgorithm::Scrypt => {
+                let params = scrypt::Params::new(
+                    self.params.log_n,
+                    self.params.block_size,
+                    self.params.parallelism,
+                    KEY_LEN,
+                )
+                .map_err(|e| anyhow::anyhow!("Invalid scrypt params: {}", e))?;
+                scrypt::scrypt(password, &self.salt, &params, &mut key)
+                    .map_err(|e| anyhow::anyhow!("Scrypt derive key failed: {}", e))?;
+            }
+            PasswordHashAlgorithm::Bcrypt => unreachable!("rejected by generate and read_from"),
+        }
+        Ok(key)
+    }
+}
+
+/// Reject params above the bounds, zero and other invalid values are left to the kdf itself
+fn check_kdf_params(kdf: PasswordHashAlgorithm, params: &HashParams) -> Result<()> {
+    let too_large = |name: &str, value: u64, max: u64| match value > max {
+        true => Err(anyhow::anyhow!(
+            "{name} {value} is too large, at most {max}"
+        )),
+        false => Ok(()),
+    };
+    match kdf {
+        PasswordHashAlgorithm::Argon2id => {
+            too_large(
+                "Argon2 memory cost in KiB",
+                params.memory.into(),
+                MAX_ARGON2_MEMORY.into(),
+            )?;
+            too_large(
+                "Argon2 iterations",
+                params.iterations.into(),
+                MAX_ARGON2_ITERATIONS.into(),
+            )?;
+            too_large(
+                "Argon2 parallelism",
+                params.parallelism.into(),
+                MAX_PARALLELISM.into(),
+            )?;
+        }
+        PasswordHashAlgorithm::Scrypt => {
+            too_large("Scrypt log_n", params.log_n.into(), MAX_SCRYPT_LOG_N.into())?;
+            too_large(
+                "Scrypt block size",
+                params.block_size.into(),
+                MAX_SCRYPT_BLOCK_SIZE.into(),
+            )?;
+            too_large(
+                "Scrypt parallelism",
+                params.parallelism.into(),
+                MAX_PARALLELISM.into(),
+            )?;
+            too_large(
+                "Scrypt memory in bytes",
+                (128 * u64::from(params.block_size)) << params.log_n,
+                MAX_SCRYPT_MEMORY,
+            )?;
+        }
+        PasswordHashAlgorithm::Bcrypt => {}
+    }
+    Ok(())
+}
+
+fn read_array<const N: usize>(reader: &mut impl Read) -> Result<[u8; N]> {
+    let mut buf = [0u8; N];
+    reader
+        .read_exact(&mut buf)
+        .context("Invalid ciphertext: header is truncated")?;
+    Ok(buf)
+}
+
+/// Where the file key comes from
+#[derive(Debug, Clone, Copy)]
+pub enum KeySource {
+    Key,
+    Password(PasswordHeader),
+}
+
+#[derive(Debug, Clone)]
+pub struct ContainerHeader {
+    pub aead: AeadAlgorithm,
+    pub source: KeySource,
+    /// Stored in clear but authenticated, e.g. a file name or purpose
+    pub aad: Vec<u8>,
+}
+
+impl ContainerHeader {
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let aad_len = u16::try_from(self.aad.len())
+            .map_err(|_| anyhow::anyhow!("Associated data must be at most {} bytes", u16::MAX))?;
+        let mut header = CONTAINER_MAGIC.to_vec();
+        header.extend_from_slice(&[CONTAINER_VERSION, aead_id(self.aead)]);
+        match &self.source {
+            KeySource::Key => header.push(SOURCE_KEY),
+            KeySource::Password(password) => {
+                header.push(SOURCE_PASSWORD);
+                password.write_to(&mut header)?;
+            }
+        }
+        header.extend_from_slice(&aad_len.to_be_bytes());
+        header.extend_from_slice(&self.aad);
+        Ok(header)
+    }
+
+    pub fn read_from(mut reader: impl Read) -> Result<Self> {
+        let mut magic = [0u8; CONTAINER_MAGIC.len()];
+        if read_full(&mut reader, &mut magic)? < magic.len() || &magic != CONTAINER_MAGIC {
+            return Err(anyhow::anyhow!(
+                "Not an rcli ciphertext, use --legacy for files from older versions"
+            ));
+        }
+        match read_array(&mut reader)? {
+            [CONTAINER_VERSION] => {}
+            [version] => return Err(anyhow::anyhow!("Unsupported ciphertext version {version}")),
+        }
+        let [aead] = read_array(&mut reader)?;
+        let aead = aead_from_id(aead)?;
+        let source = match read_array(&mut reader)? {
+            [SOURCE_KEY] => KeySource::Key,
+            [SOURCE_PASSWORD] => KeySource::Password(PasswordHeader::read_from(&mut reader)?),
+            [source] => return Err(anyhow::anyhow!("Unknown key source {source}")),
+        };
+        let aad_len = u16::from_be_bytes(read_array(&mut reader)?);
+        let mut aad = vec![0u8; aad_len as usize];
+        reader
+            .read_exact(&mut aad)
+            .context("Invalid ciphertext: header is truncated")?;
+        Ok(Self { aead, source, aad })
+    }
+}
+
+/// Write the header, then the body encrypted with `key` bound to the header
+pub fn encrypt_container(
+    reader: impl Read,
+    mut writer: impl Write,
+    key: &[u8],
+    header: &ContainerHeader,
+) -> Result<()> {
+    let header_bytes = header.to_bytes()?;
+    writer.write_all(&header_bytes)?;
+    encrypt_stream(reader, writer, header.aead, key, &header_bytes)
+}
+
+/// Decrypt the body after `header` with the key file or, for password encrypted
+/// input, a key derived from `password`, which is only called then
+pub fn decrypt_body(
+    header: &ContainerHeader,
+    reader: impl Read,
+    writer: impl Write,
+    key: Option<&[u8]>,
+    password: impl FnOnce() -> Result<String>,
+) -> Result<()> {
+    let header_bytes = header.to_bytes()?;
+    match &header.source {
+        KeySource::Key => {
+            let key = key.context("Input is not password encrypted, a key is required")?;
+            decrypt_stream(reader, writer, header.aead, key, &header_bytes)?;
+        }
+        KeySource::Password(password_header) => {
+            let key = password_header.derive_key(password()?.as_bytes())?;
+            decrypt_stream(reader, writer, header.aead, &key, &header_bytes)
+                .map_err(|e| e.context("Wrong password or corrupted ciphertext"))?;
+        }
+    }
+    Ok(())
+}
+
+pub fn process_text_encrypt(
+    input: &str,
+    output: &str,
+    key: &str,
+    algorithm: AeadAlgorithm,
+    aad: Option<&str>,
+) -> Result<()> {
+    let key = read_buffer_from_input(key)?;
+    let header = ContainerHeader {
+        aead: algorithm,
+        source: KeySource::Key,
+        aad: aad.unwrap_or_default().into(),
+    };
+    // Stdin drops its final newline like `text sign` does
+    let reader = open_input_trimmed(input)?;
+    let writer = open_output(output)?;
+    encrypt_container(reader, writer, &key, &header).context("Encrypt failed")
+}
+
+pub fn process_text_encrypt_password(
+    input: &str,
+    output: &str,
+    password: &str,
+    kdf: PasswordHashAlgorithm,
+    params: HashParams,
+    algorithm: AeadAlgorithm,
+    aad: Option<&str>,
+) -> Result<()> {
+    let password_header = PasswordHeader::generate(kdf, params)?;
+    let key = password_header.derive_key(password.as_bytes())?;
+    let header = ContainerHeader {
+        aead: algorithm,
+        source: KeySource::Password(password_header),
+        aad: aad.unwrap_or_default().into(),
+    };
+    // Stdin drops its final newline like `text sign` does
+    let reader = open_input_trimmed(input)?;
+    let writer = open_output(output)?;
+    encrypt_container(reader, writer, &key, &header).context("Encrypt failed")
+}
+
+/// Decrypt a container, when `aad` is given it must match the stored associated data.
+/// The output file is only created once the whole input has authenticated
+pub fn process_text_decrypt(
+    input: &str,
+    output: &str,
+    key: Option<&str>,
+    password: impl FnOnce() -> Result<String>,
+    aad: Option<&str>,
+) -> Result<()> {
+    decrypt_input(open_input(input)?, output, key, password, aad)
+}
+
+fn decrypt_input(
+    reader: impl Read,
+    output: &str,
+    key: Option<&str>,
+    password: impl FnOnce() -> Result<String>,
+    aad: Option<&str>,
+) -> Result<()> {
+    // A single read may return less than the magic, e.g. from a pipe
+    let (head, reader) = read_head(reader, AGE_MAGIC.len())?;
+    let mut reader = BufReader::new(reader);
+    if head.starts_with(AGE_MAGIC) {
+        let key = key.context("Input is encrypted to recipients, an identity file is required")?;
+        if aad.is_some() {
+            return Err(anyhow::anyhow!("Age files don't carry associated data"));
+        }
+        let identities = load_identities(key)?;
+        return write_output_atomic(output, |writer| {
+            decrypt_with_identities(reader, writer, &identities)
+        });
+    }
+
+    let key = key.map(read_buffer_from_input).transpose()?;
+    // Check the associated data before any plaintext is written
+    let header = ContainerHeader::read_from(&mut reader)?;
+    if let Some(aad) = aad
+        && aad.as_bytes() != header.aad
+    {
+        return Err(anyhow::anyhow!(
+            "Associated data doesn't match, found {:?}",
+            String::from_utf8_lossy(&header.aad)
+        ));
+    }
+    if !header.aad.is_empty() {
+        eprintln!("Associated data: {}", String::from_utf8_lossy(&header.aad));
+    }
+
+    // Segments are written as they authenticate, a later failure must not leave them behind
+    write_output_atomic(output, |writer| {
+        decrypt_body(&header, reader, writer, key.as_deref(), password)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        AeadAlgorithm, HashParams, PasswordHashAlgorithm,
+        process::process_aead::{
+            ContainerHeader, KeySource, PasswordHeader, SEGMENT_LEN, decrypt_body, decrypt_input,
+            decrypt_stream, encrypt_container, encrypt_stream, process_text_decrypt,
+            process_text_encrypt,
+        },
+        process::process_age::encrypt_to_recipients,
+        utils::{TrimFinalNewline, test::Trickle},
+    };
+
+    const KEY: [u8; 32] = [7; 32];
+
+    fn decrypt_container(
+        mut sealed: &[u8],
+        writer: &mut Vec<u8>,
+        key: Option<&[u8]>,
+        password: impl FnOnce() -> anyhow::Result<String>,
+    ) -> anyhow::Result<ContainerHeader> {
+        let header = ContainerHeader::read_from(&mut sealed)?;
+        decrypt_body(&header, sealed, writer, key, password)?;
+        Ok(header)
+    }
+
+    const CHACHA: AeadAlgorithm = AeadAlgorithm::ChaCha20Poly1305;
+
+    fn encrypt(data: &[u8]) -> Vec<u8> {
+        let mut sealed = Vec::new();
+        encrypt_stream(data, &mut sealed, CHACHA, &KEY, b"").unwrap();
+        sealed
+    }
+
+    #[test]
+    fn test_stream_round_trip() {
+        for (algorithm, prefix) in [
+            (AeadAlgorithm::ChaCha20Poly1305, 7),
+            (AeadAlgorithm::XChaCha20Poly1305, 19),
+            (AeadAlgorithm::Aes256Gcm, 7),
+            (AeadAlgorithm::Aes256GcmSiv, 7),
+        ] {
+            for len in [0, 1, SEGMENT_LEN - 1, SEGMENT_LEN, SEGMENT_LEN * 2 + 5] {
+                let data = (0..len).map(|i| i as u8).collect::<Vec<_>>();
+                let mut sealed = Vec::new();
+                encrypt_stream(&data[..], &mut sealed, algorithm, &KEY, b"").unwrap();
+                let segments = len.div_ceil(SEGMENT_LEN).max(1);
+                assert_eq!(sealed.len(), prefix + len + segments * 16);
+
+                let mut opened = Vec::new();
+                decrypt_stream(&sealed[..], &mut opened, algorithm, &KEY, b"").unwrap();
+                assert_eq!(opened, data, "{algorithm} {len}");
+                assert!(decrypt_stream(&sealed[..], Vec::new(), algorithm, &[8; 32], b"").is_err());
+                assert!(decrypt_stream(&sealed[..], Vec::new(), algorithm, &KEY, b"aad").is_err());
+            }
+            assert!(encrypt_stream(&b"data"[..], Vec::new(), algorithm, b"short", b"").is_err());
+        }
+
+        let sealed = encrypt(b"data");
+        let other = AeadAlgorithm::Aes256Gcm;
+        assert!(decrypt_stream(&sealed[..], Vec::new(), other, &KEY, b"").is_err());
+    }
+
+    #[test]
+    fn test_stream_detects_truncation_and_reordering() {
+        let data = vec![1u8; SEGMENT_LEN * 3];
+        let sealed = encrypt(&data);
+        let segment = SEGMENT_LEN + 16;
+
+        // Dropping the last segment leaves a full segment without the last flag
+        let truncated = &sealed[..7 + segment * 2];
+        let err = decrypt_stream(truncated, Vec::new(), CHACHA, &KEY, b"").unwrap_err();
+        assert!(err.to_string().contains("segment 1"));
+
+        let mut reordered = sealed.clone();
+        reordered[7..7 + segment].copy_from_slice(&sealed[7 + segment..7 + segment * 2]);
+        reordered[7 + segment..7 + segment * 2].copy_from_slice(&sealed[7..7 + segment]);
+        let err = decrypt_stream(&reordered[..], Vec::new(), CHACHA, &KEY, b"").unwrap_err();
+        assert!(err.to_string().contains("segment 0"));
+
+        let mut extended = sealed.clone();
+        extended.extend_from_slice(&[0; 16]);
+        assert!(decrypt_stream(&extended[..], Vec::new(), CHACHA, &KEY, b"").is_err());
+    }
+
+    #[test]
+    fn test_container_header_is_authenticated() {
+        let header = ContainerHeader {
+            aead: AeadAlgorithm::XChaCha20Poly1305,
+            source: KeySource::Key,
+            aad: b"backup.tar".to_vec(),
+        };
+        let mut sealed = Vec::new();
+        encrypt_container(&b"data"[..], &mut sealed, &KEY, &header).unwrap();
+        assert!(sealed.starts_with(b"rcli-enc\x01\x02\x01\x00\x0abackup.tar"));
+
+        let mut opened = Vec::new();
+        let parsed =
+            decrypt_container(&sealed[..], &mut opened, Some(&KEY), || unreachable!()).unwrap();
+        assert_eq!(opened, b"data");
+        assert_eq!(parsed.aad, b"backup.tar");
+
+        let mut tampered = sealed.clone();
+        tampered[13] = b'B';
+        assert!(
+            decrypt_container(
+                &tampered[..],
+                &mut Vec::new(),
+                Some(&KEY),
+                || unreachable!()
+            )
+            .is_err()
+        );
+        for (offset, byte) in [(0, b'x'), (8, 2), (9, 9), (10, 9)] {
+            let mut invalid = sealed.clone();
+            invalid[offset] = byte;
+            assert!(
+                decrypt_container(&invalid[..], &mut Vec::new(), Some(&KEY), || unreachable!())
+                    .is_err()
+            );
+        }
+        assert!(decrypt_container(&sealed[..], &mut Vec::new(), None, || unreachable!()).is_err());
+    }
+
+    #[test]
+    fn test_password_container_round_trip() {
+        // Small params keep the test fast, real defaults come from HashParams
+        let params = HashParams {
+            memory: 64,
+            iterations: 1,
+            log_n: 4,
+            ..HashParams::default()
+        };
+        for kdf in [
+            PasswordHashAlgorithm::Argon2id,
+            PasswordHashAlgorithm::Scrypt,
+        ] {
+            let password = PasswordHeader::generate(kdf, params).unwrap();
+            let key = password.derive_key(b"hunter2").unwrap();
+            let header = ContainerHeader {
+                aead: AeadAlgorithm::Aes256GcmSiv,
+                source: KeySource::Password(password),
+                aad: Vec::new(),
+            };
+            let mut sealed = Vec::new();
+            encrypt_container(&b"secret notes"[..], &mut sealed, &key, &header).unwrap();
+
+            let mut opened = Vec::new();
+            decrypt_container(&sealed[..], &mut opened, None, || Ok("hunter2".into())).unwrap();
+            assert_eq!(opened, b"secret notes");
+            assert!(
+                decrypt_container(&sealed[..], &mut Vec::new(), None, || Ok("wrong".into()))
+                    .is_err()
+            );
+        }
+        assert!(PasswordHeader::generate(PasswordHashAlgorithm::Bcrypt, params).is_err());
+    }
+
+    #[test]
+    fn test_failed_decrypt_leaves_no_output() {
+        let dir = std::env::temp_dir().join(format!("rcli-decrypt-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+        std::fs::write(path("key"), KEY).unwrap();
+        std::fs::write(path("plain"), vec![1u8; SEGMENT_LEN * 2 + 5]).unwrap();
+        process_text_encrypt(&path("plain"), &path("sealed"), &path("key"), CHACHA, None).unwrap();
+
+        let no_password = || unreachable!();
+        process_text_decrypt(
+            &path("sealed"),
+            &path("out"),
+            Some(&path("key")),
+            no_password,
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            std::fs::read(path("out")).unwrap(),
+            std::fs::read(path("plain")).unwrap()
+        );
+        std::fs::remove_file(path("out")).unwrap();
+
+        // The first segments authenticate before the tampered last one fails
+        let mut sealed = std::fs::read(path("sealed")).unwrap();
+        *sealed.last_mut().unwrap() ^= 1;
+        std::fs::write(path("sealed"), sealed).unwrap();
+        let result = process_text_decrypt(
+            &path("sealed"),
+            &path("out"),
+            Some(&path("key")),
+            no_password,
+            None,
+        );
+        assert!(result.is_err());
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 3);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_password_header_bounds() {
+        let read_back = |kdf, params| {
+            let header = PasswordHeader {
+                kdf,
+                params,
+                salt: [0; 16],
+            };
+            let mut bytes = Vec::new();
+            header.write_to(&mut bytes).unwrap();
+            PasswordHeader::read_from(&bytes[..])
+        };
+        let argon2 = |memory, iterations, parallelism| {
+            let params = HashParams {
+                memory,
+                iterations,
+                parallelism,
+                ..HashParams::default()
+            };
+            read_back(PasswordHashAlgorithm::Argon2id, params)
+        };
+        let scrypt = |log_n, block_size, parallelism| {
+            let params = HashParams {
+                log_n,
+                block_size,
+                parallelism,
+                ..HashParams::default()
+            };
+            read_back(PasswordHashAlgorithm::Scrypt, params)
+        };
+
+        assert!(argon2(4 * 1024 * 1024, 64, 16).is_ok());
+        assert!(argon2(4 * 1024 * 1024 + 1, 1, 1).is_err());
+        assert!(argon2(64, 65, 1).is_err());
+        assert!(argon2(64, 1, 17).is_err());
+
+        assert!(scrypt(22, 8, 16).is_ok());
+        assert!(scrypt(23, 1, 1).is_err());
+        assert!(scrypt(4, 33, 1).is_err());
+        assert!(scrypt(4, 8, 17).is_err());
+        // Each alone is in bounds, together they need 16 GiB
+        assert!(scrypt(22, 32, 1).is_err());
+        assert!(scrypt(20, 32, 1).is_ok());
+
+        let params = HashParams {
+            iterations: 65,
+            ..HashParams::default()
+        };
+        assert!(PasswordHeader::generate(PasswordHashAlgorithm::Argon2id, params).is_err());
+    }
+
+    #[test]
+    fn test_decrypt_detects_age_from_short_reads() {
+        use age::secrecy::ExposeSecret;
+
+        let identity = age::x25519::Identity::generate();
+        let mut sealed = Vec::new();
+        encrypt_to_recipients(&b"hello age"[..], &mut sealed, &[identity.to_public()]).unwrap();
+
+        let dir = std::env::temp_dir();
+        let key = dir.join(format!("rcli-age-{}.sk", std::process::id()));
+        let output = dir.join(format!("rcli-age-{}.txt", std::process::id()));
+        std::fs::write(&key, identity.to_string().expose_secret()).unwrap();
+        let (key, output) = (key.to_str().unwrap(), output.to_str().unwrap());
+
+        decrypt_input(Trickle(&sealed), output, Some(key), || unreachable!(), None).unwrap();
+        assert_eq!(std::fs::read(output).unwrap(), b"hello age");
+        std::fs::remove_file(key).unwrap();
+        std::fs::remove_file(output).unwrap();
+    }
+
+    #[test]
+    fn test_stdin_round_trip_drops_the_final_newline() {
+        // `echo hello | rcli text encrypt` decrypts to what `text sign` would have signed
+        let header = ContainerHeader {
+            aead: CHACHA,
+            source: KeySource::Key,
+            aad: Vec::new(),
+        };
+        let stdin = TrimFinalNewline::new(&b"hello\n"[..]);
+        let mut sealed = Vec::new();
+        encrypt_container(stdin, &mut sealed, &KEY, &header).unwrap();
+        let mut opened = Vec::new();
+        decrypt_container(&sealed[..], &mut opened, Some(&KEY), || unreachable!()).unwrap();
+        assert_eq!(opened, b"hello");
+    }
+}
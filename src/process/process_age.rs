@@ -0,0 +1,178 @@
+use std::{
+    io::{self, BufRead, Read, Write},
+    path::Path,
+    time::SystemTime,
+};
+
+use age::{
+    Decryptor, Encryptor,
+    secrecy::ExposeSecret,
+    x25519::{Identity, Recipient},
+};
+use anyhow::{Context, Result};
+
+use crate::{open_input, open_output};
+
+/// Every age v1 file starts with this line, used to tell it apart from the rcli container
+pub const AGE_MAGIC: &[u8] = b"age-encryption.org/v1\n";
+
+/// A new X25519 identity in the age-keygen layout and the matching `age1...` recipient
+pub fn generate_x25519() -> Vec<Vec<u8>> {
+    let identity = Identity::generate();
+    let recipient = identity.to_public();
+    let created = humantime::format_rfc3339_seconds(SystemTime::now());
+    let sk = format!(
+        "# created: {created}\n# public key: {recipient}\n{}\n",
+        identity.to_string().expose_secret()
+    );
+    vec![sk.into_bytes(), format!("{recipient}\n").into_bytes()]
+}
+
+/// Non empty lines of `content` that are not `#` comments
+fn key_lines(content: &str) -> impl Iterator<Item = (usize, &str)> {
+    content
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+}
+
+/// Each value is an `age1...` recipient, or a file with one recipient per line
+pub fn parse_recipients(values: &[String]) -> Result<Vec<Recipient>> {
+    let mut recipients = Vec::new();
+    for value in values {
+        if value.starts_with("age1") && !Path::new(value).exists() {
+            let recipient = value
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid recipient {value}: {e}"))?;
+            recipients.push(recipient);
+            continue;
+        }
+
+        let content = std::fs::read_to_string(value)
+            .with_context(|| format!("Read recipients file {value} failed"))?;
+        for (line_no, line) in key_lines(&content) {
+            let recipient = line
+                .parse()
+                .map_err(|e| anyhow::anyhow!("{value}: {line_no}: invalid recipient: {e}"))?;
+            recipients.push(recipient);
+        }
+    }
+    if recipients.is_empty() {
+        return Err(anyhow::anyhow!("No recipients given"));
+    }
+    Ok(recipients)
+}
+
+/// Identities from an age identity file, as written by `text generate --format x25519`
+/// or age-keygen
+pub fn load_identities(path: &str) -> Result<Vec<Identity>> {
+    let mut content = String::new();
+    open_input(path)?
+        .read_to_string(&mut content)
+        .with_context(|| format!("Read identity file {path} failed"))?;
+    parse_identities(&content, path)
+}
+
+/// Identities from the content of an identity file, `path` is only used in errors
+pub fn parse_identities(content: &str, path: &str) -> Result<Vec<Identity>> {
+    let identities = key_lines(content)
+        .map(|(line_no, line)| {
+            line.parse()
+                .map_err(|e| anyhow::anyhow!("{path}: {line_no}: invalid identity: {e}"))
+        })
+        .collect::<Result<Vec<Identity>>>()?;
+    if identities.is_empty() {
+        return Err(anyhow::anyhow!("No identities found in {path}"));
+    }
+    Ok(identities)
+}
+
+/// Encrypt to every recipient, any one of their identities can decrypt
+pub fn encrypt_to_recipients(
+    mut reader: impl Read,
+    writer: impl Write,
+    recipients: &[Recipient],
+) -> Result<()> {
+    let encryptor =
+        Encryptor::with_recipients(recipients.iter().map(|r| r as &dyn age::Recipient))?;
+    let mut writer = encryptor.wrap_output(writer)?;
+    io::copy(&mut reader, &mut writer)?;
+    writer.finish()?.flush()?;
+    Ok(())
+}
+
+pub fn decrypt_with_identities(
+    reader: impl BufRead,
+    mut writer: impl Write,
+    identities: &[Identity],
+) -> Result<()> {
+    let decryptor = Decryptor::new_buffered(reader).context("Invalid age file")?;
+    let mut reader = decryptor
+        .decrypt(identities.iter().map(|i| i as &dyn age::Identity))
+        .context("None of the identities can decrypt this file")?;
+    io::copy(&mut reader, &mut writer).context("Decrypt failed, the file is corrupted")?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Encrypt `input` to the recipients in the age v1 format
+pub fn process_text_encrypt_recipients(
+    input: &str,
+    output: &str,
+    recipients: &[String],
+) -> Result<()> {
+    let recipients = parse_recipients(recipients)?;
+    let reader = open_input(input)?;
+    let writer = open_output(output)?;
+    encrypt_to_recipients(reader, writer, &recipients)
+}
+
+#[cfg(test)]
+mod test {
+    use age::x25519::{Identity, Recipient};
+
+    use crate::process::process_age::{
+        AGE_MAGIC, decrypt_with_identities, encrypt_to_recipients, generate_x25519,
+        load_identities, parse_recipients,
+    };
+
+    #[test]
+    fn test_recipients_round_trip() {
+        let (alice, bob, eve) = (
+            Identity::generate(),
+            Identity::generate(),
+            Identity::generate(),
+        );
+        let recipients = [alice.to_public(), bob.to_public()];
+        let data = vec![42u8; 100_000];
+        let mut sealed = Vec::new();
+        encrypt_to_recipients(&data[..], &mut sealed, &recipients).unwrap();
+        assert!(sealed.starts_with(AGE_MAGIC));
+
+        for identity in [alice, bob] {
+            let mut opened = Vec::new();
+            decrypt_with_identities(&sealed[..], &mut opened, &[identity]).unwrap();
+            assert_eq!(opened, data);
+        }
+        assert!(decrypt_with_identities(&sealed[..], Vec::new(), &[eve]).is_err());
+        assert!(encrypt_to_recipients(&data[..], Vec::new(), &[]).is_err());
+    }
+
+    #[test]
+    fn test_generated_keys_parse() {
+        let keys = generate_x25519();
+        let sk = String::from_utf8(keys[0].clone()).unwrap();
+        let pk = String::from_utf8(keys[1].clone()).unwrap();
+        assert!(sk.contains(&format!("# public key: {}", pk.trim())));
+
+        let identity: Identity = sk.lines().last().unwrap().parse().unwrap();
+        let recipient: Recipient = pk.trim().parse().unwrap();
+        assert_eq!(identity.to_public().to_string(), recipient.to_string());
+        let parsed = parse_recipients(&[pk.trim().to_string()]).unwrap();
+        assert_eq!(parsed[0].to_string(), recipient.to_string());
+
+        assert!(parse_recipients(&["age1invalid".into()]).is_err());
+        assert!(load_identities("fixtures/juventus.csv").is_err());
+    }
+}
@@ -1,41 +1,810 @@
+use std::io::{self, Read, Write};
+
 use anyhow::{Context, Result};
-use base64::engine::general_purpose::URL_SAFE;
-use base64::prelude::*;
+use base64::{
+    DecodeError, alphabet,
+    engine::{
+        DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig,
+        general_purpose::{STANDARD, STANDARD_NO_PAD, URL_SAFE, URL_SAFE_NO_PAD},
+    },
+    prelude::*,
+};
+use data_encoding::{BASE32, BASE32_NOPAD, Encoding, HEXLOWER, HEXLOWER_PERMISSIVE};
+
+use crate::{
+    CodecFormat, open_input, open_input_trimmed, open_output, read_buffer_from_input,
+    utils::read_full, write_buffer_to_output,
+};
+
+const LENIENT_CONFIG: GeneralPurposeConfig =
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent);
+const LENIENT_STANDARD: GeneralPurpose = GeneralPurpose::new(&alphabet::STANDARD, LENIENT_CONFIG);
+const LENIENT_URL_SAFE: GeneralPurpose = GeneralPurpose::new(&alphabet::URL_SAFE, LENIENT_CONFIG);
 
-use crate::{Base64Format, read_buffer_from_input};
+const ASCII85_ALPHABET: &[u8; 85] =
+    b"!\"#$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstu";
+const Z85_ALPHABET: &[u8; 85] =
+    b"0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ.-:+=^!/*?&<>()[]{}@%$#";
+
+const STREAM_CHUNK: usize = 64 * 1024;
+
+/// Wrap lines at `wrap` characters when it is not 0, MIME uses 76. Input is encoded in
+/// fixed-size chunks so memory use doesn't grow with it, except for base58 and ascii85
+pub fn process_base64_encode(
+    input: &str,
+    output: &str,
+    format: CodecFormat,
+    wrap: usize,
+) -> Result<()> {
+    if stream_block(format).is_some() {
+        let reader = open_input_trimmed(input)?;
+        return encode_stream(reader, open_output(output)?, format, wrap);
+    }
 
-pub fn process_base64_encode(input: &str, format: Base64Format) -> Result<()> {
     let buf = read_buffer_from_input(input)?;
-    let result = match format {
-        Base64Format::Standard => BASE64_STANDARD.encode(buf),
-        Base64Format::UrlSafe => URL_SAFE.encode(buf),
-    };
+    let mut writer = LineWrapWriter::new(Vec::new(), wrap);
+    writer.write_all(encode_bytes(&buf, format).as_bytes())?;
+    write_buffer_to_output(output, &writer.finish()?)
+}
 
-    print!("{}", result);
-    Ok(())
+/// Decode in fixed-size chunks, except for base58 and ascii85 which need the whole input.
+/// Whitespace and newlines are always ignored, so wrapped input decodes in strict mode too.
+pub fn process_base64_decode(
+    input: &str,
+    output: &str,
+    format: CodecFormat,
+    lenient: bool,
+) -> Result<()> {
+    if stream_block(format).is_none() {
+        return write_buffer_to_output(output, &decode_input(input, format, lenient)?);
+    }
+
+    let reader = open_input(input)?;
+    let writer = open_output(output)?;
+    decode_stream(reader, writer, format, lenient)
+        .with_context(|| format!("Decode input {format} failed"))
+}
+
+/// Decode the whole input and convert it to text, invalid utf8 is replaced
+pub fn process_base64_decode_text(
+    input: &str,
+    format: CodecFormat,
+    lenient: bool,
+) -> Result<String> {
+    let result = decode_input(input, format, lenient)?;
+    Ok(String::from_utf8_lossy(&result).into_owned())
 }
 
-pub fn process_base64_decode(input: &str, format: Base64Format) -> Result<Vec<u8>> {
+fn decode_input(input: &str, format: CodecFormat, lenient: bool) -> Result<Vec<u8>> {
     let buf = read_buffer_from_input(input)?;
-    let result = match format {
-        Base64Format::Standard => BASE64_STANDARD
-            .decode(buf)
-            .context("Decode input base64 failed")?,
-        Base64Format::UrlSafe => URL_SAFE.decode(buf).context("Decode input base64 failed")?,
+    decode_bytes(&buf, format, lenient).with_context(|| format!("Decode input {format} failed"))
+}
+
+/// Auto only changes decoding, it encodes as standard. Z85 input that isn't a multiple
+/// of 4 bytes ends in a short group, which only lenient decoding accepts.
+pub fn encode_bytes(data: &[u8], format: CodecFormat) -> String {
+    match format {
+        CodecFormat::Standard | CodecFormat::Auto => STANDARD.encode(data),
+        CodecFormat::StandardNoPad => STANDARD_NO_PAD.encode(data),
+        CodecFormat::UrlSafe => URL_SAFE.encode(data),
+        CodecFormat::UrlSafeNoPad => URL_SAFE_NO_PAD.encode(data),
+        CodecFormat::Base32 => BASE32.encode(data),
+        CodecFormat::Base32NoPad => BASE32_NOPAD.encode(data),
+        CodecFormat::Base58 => bs58::encode(data).into_string(),
+        CodecFormat::Ascii85 => base85_encode(data, ASCII85_ALPHABET, true),
+        CodecFormat::Z85 => base85_encode(data, Z85_ALPHABET, false),
+        CodecFormat::Hex => HEXLOWER.encode(data),
+    }
+}
+
+/// Whitespace is ignored in both modes, so wrapped input decodes. Otherwise strict mode
+/// only accepts the canonical form, lenient mode also ignores padding and letter case
+/// where the codec allows it
+pub fn decode_bytes(data: &[u8], format: CodecFormat, lenient: bool) -> Result<Vec<u8>> {
+    Symbols::new(data, 0, format, lenient).decode(format, lenient)
+}
+
+/// Encoded symbols left once ignored characters are dropped, with the input offset of
+/// each one so errors point into the original input rather than the cleaned symbols
+struct Symbols {
+    data: Vec<u8>,
+    offsets: Vec<usize>,
+    /// Input offset just past the last symbol
+    end: usize,
+}
+
+impl Symbols {
+    /// Whitespace is always dropped, lenient mode also drops the padding, separators and
+    /// delimiters the codec ignores. `start` is the input offset of `input`.
+    fn new(input: &[u8], start: usize, format: CodecFormat, lenient: bool) -> Self {
+        let mut symbols = Self {
+            data: Vec::with_capacity(input.len()),
+            offsets: Vec::with_capacity(input.len()),
+            end: start + input.len(),
+        };
+        for (i, c) in input.iter().enumerate() {
+            let c = match (format, lenient) {
+                (_, _) if c.is_ascii_whitespace() => continue,
+                (CodecFormat::Base32 | CodecFormat::Base32NoPad, true) if *c == b'=' => continue,
+                (CodecFormat::Base32 | CodecFormat::Base32NoPad, true) => c.to_ascii_uppercase(),
+                (CodecFormat::Hex, true) if *c == b':' => continue,
+                _ => *c,
+            };
+            symbols.data.push(c);
+            symbols.offsets.push(start + i);
+        }
+
+        match (format, lenient) {
+            (CodecFormat::Hex, true) if start == 0 && symbols.data.starts_with(b"0x") => {
+                symbols.drain(2);
+            }
+            (CodecFormat::Ascii85, true) => {
+                if symbols.data.starts_with(b"<~") {
+                    symbols.drain(2);
+                }
+                if symbols.data.ends_with(b"~>") {
+                    let len = symbols.data.len() - 2;
+                    symbols.end = symbols.offsets[len];
+                    symbols.data.truncate(len);
+                    symbols.offsets.truncate(len);
+                }
+            }
+            _ => {}
+        }
+        symbols
+    }
+
+    fn extend(&mut self, other: Symbols) {
+        self.data.extend(other.data);
+        self.offsets.extend(other.offsets);
+        self.end = other.end;
+    }
+
+    /// Remove the first `n` symbols
+    fn drain(&mut self, n: usize) {
+        self.data.drain(..n);
+        self.offsets.drain(..n);
+    }
+
+    fn offset(&self, i: usize) -> usize {
+        self.offsets.get(i).copied().unwrap_or(self.end)
+    }
+
+    fn decode(&self, format: CodecFormat, lenient: bool) -> Result<Vec<u8>> {
+        self.decode_prefix(self.data.len(), format, lenient)
+    }
+
+    fn decode_prefix(&self, len: usize, format: CodecFormat, lenient: bool) -> Result<Vec<u8>> {
+        decode_symbols(&self.data[..len], format, lenient, &|i| self.offset(i))
+    }
+}
+
+/// Decode symbols the lenient cleanup has already been applied to, `offset_of` maps an
+/// index into `data` back to the input offset reported in errors
+fn decode_symbols(
+    data: &[u8],
+    format: CodecFormat,
+    lenient: bool,
+    offset_of: &dyn Fn(usize) -> usize,
+) -> Result<Vec<u8>> {
+    let result = match (format, lenient) {
+        (CodecFormat::Standard, false) => base64_decode(&STANDARD, data, offset_of)?,
+        (CodecFormat::StandardNoPad, false) => base64_decode(&STANDARD_NO_PAD, data, offset_of)?,
+        (CodecFormat::UrlSafe, false) => base64_decode(&URL_SAFE, data, offset_of)?,
+        (CodecFormat::UrlSafeNoPad, false) => base64_decode(&URL_SAFE_NO_PAD, data, offset_of)?,
+        (CodecFormat::Standard | CodecFormat::StandardNoPad, true) => {
+            base64_decode(&LENIENT_STANDARD, data, offset_of)?
+        }
+        (CodecFormat::UrlSafe | CodecFormat::UrlSafeNoPad, true) => {
+            base64_decode(&LENIENT_URL_SAFE, data, offset_of)?
+        }
+        (CodecFormat::Auto, _) => decode_base64_auto(data, offset_of)?,
+        (CodecFormat::Base32, false) => encoding_decode(&BASE32, data, format, offset_of)?,
+        (CodecFormat::Base32NoPad, _) | (CodecFormat::Base32, true) => {
+            encoding_decode(&BASE32_NOPAD, data, format, offset_of)?
+        }
+        (CodecFormat::Base58, _) => bs58::decode(data).into_vec().map_err(|e| match e {
+            bs58::decode::Error::InvalidCharacter { character, index } => anyhow::anyhow!(
+                "Invalid base58 character {character:?} at offset {}",
+                offset_of(index)
+            ),
+            e => e.into(),
+        })?,
+        (CodecFormat::Ascii85, _) => base85_decode(data, ASCII85_ALPHABET, true, offset_of)?,
+        (CodecFormat::Z85, false) => {
+            if !data.len().is_multiple_of(5) {
+                return Err(anyhow::anyhow!(
+                    "Invalid z85 length {}, must be a multiple of 5",
+                    data.len()
+                ));
+            }
+            base85_decode(data, Z85_ALPHABET, false, offset_of)?
+        }
+        (CodecFormat::Z85, true) => base85_decode(data, Z85_ALPHABET, false, offset_of)?,
+        (CodecFormat::Hex, false) => encoding_decode(&HEXLOWER, data, format, offset_of)?,
+        (CodecFormat::Hex, true) => encoding_decode(&HEXLOWER_PERMISSIVE, data, format, offset_of)?,
     };
 
     Ok(result)
 }
 
+fn base64_decode(
+    engine: &GeneralPurpose,
+    data: &[u8],
+    offset_of: &dyn Fn(usize) -> usize,
+) -> Result<Vec<u8>> {
+    engine.decode(data).map_err(|e| base64_error(e, offset_of))
+}
+
+fn encoding_decode(
+    encoding: &Encoding,
+    data: &[u8],
+    format: CodecFormat,
+    offset_of: &dyn Fn(usize) -> usize,
+) -> Result<Vec<u8>> {
+    encoding.decode(data).map_err(|e| {
+        anyhow::anyhow!(
+            "Invalid {format} input: {} at offset {}",
+            e.kind,
+            offset_of(e.position)
+        )
+    })
+}
+
+/// Accept the standard and url-safe alphabets mixed, with or without padding and with
+/// whitespace anywhere. Errors report offsets into the original input.
+fn decode_base64_auto(data: &[u8], offset_of: &dyn Fn(usize) -> usize) -> Result<Vec<u8>> {
+    let mut symbols = Vec::with_capacity(data.len());
+    let mut offsets = Vec::with_capacity(data.len());
+    let mut padding = None;
+    for (offset, c) in data.iter().enumerate() {
+        let symbol = match c {
+            c if c.is_ascii_whitespace() => continue,
+            b'=' => {
+                padding.get_or_insert(offset);
+                continue;
+            }
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'+' | b'/' => *c,
+            b'-' => b'+',
+            b'_' => b'/',
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "Invalid base64 character {:?} at offset {}",
+                    *c as char,
+                    offset_of(offset)
+                ));
+            }
+        };
+        if let Some(padding) = padding {
+            return Err(anyhow::anyhow!(
+                "Unexpected base64 character {:?} at offset {} after padding at offset {}",
+                *c as char,
+                offset_of(offset),
+                offset_of(padding)
+            ));
+        }
+        symbols.push(symbol);
+        offsets.push(offset);
+    }
+
+    if symbols.len() % 4 == 1 {
+        return Err(anyhow::anyhow!(
+            "Invalid base64 length: dangling final character {:?} at offset {}",
+            symbols[symbols.len() - 1] as char,
+            offset_of(offsets[offsets.len() - 1])
+        ));
+    }
+
+    LENIENT_STANDARD
+        .decode(&symbols)
+        .map_err(|e| base64_error(e, &|i| offset_of(offsets[i])))
+}
+
+fn base64_error(e: DecodeError, offset_of: &dyn Fn(usize) -> usize) -> anyhow::Error {
+    match e {
+        DecodeError::InvalidByte(i, byte) => anyhow::anyhow!(
+            "Invalid base64 character {:?} at offset {}",
+            byte as char,
+            offset_of(i)
+        ),
+        DecodeError::InvalidLastSymbol(i, byte) => anyhow::anyhow!(
+            "Invalid final base64 character {:?} at offset {}, trailing bits are not zero",
+            byte as char,
+            offset_of(i)
+        ),
+        e => e.into(),
+    }
+}
+
+/// Raw and encoded block sizes, chunks aligned to them encode independently.
+/// Base58 is a big number conversion and ascii85 `z` groups break alignment, so they can't stream
+fn stream_block(format: CodecFormat) -> Option<(usize, usize)> {
+    match format {
+        CodecFormat::Standard
+        | CodecFormat::StandardNoPad
+        | CodecFormat::UrlSafe
+        | CodecFormat::UrlSafeNoPad
+        | CodecFormat::Auto => Some((3, 4)),
+        CodecFormat::Base32 | CodecFormat::Base32NoPad => Some((5, 8)),
+        CodecFormat::Z85 => Some((4, 5)),
+        CodecFormat::Hex => Some((1, 2)),
+        CodecFormat::Base58 | CodecFormat::Ascii85 => None,
+    }
+}
+
+pub fn encode_stream(
+    mut reader: impl Read,
+    writer: impl Write,
+    format: CodecFormat,
+    wrap: usize,
+) -> Result<()> {
+    let (raw, _) = stream_block(format)
+        .with_context(|| format!("Format {format} doesn't support streaming"))?;
+    let mut buf = vec![0u8; STREAM_CHUNK / raw * raw];
+    let mut writer = LineWrapWriter::new(writer, wrap);
+    loop {
+        let n = read_full(&mut reader, &mut buf)?;
+        if n == 0 {
+            break;
+        }
+        writer.write_all(encode_bytes(&buf[..n], format).as_bytes())?;
+        if n < buf.len() {
+            break;
+        }
+    }
+
+    writer.finish()?.flush()?;
+    Ok(())
+}
+
+pub fn decode_stream(
+    mut reader: impl Read,
+    mut writer: impl Write,
+    format: CodecFormat,
+    lenient: bool,
+) -> Result<()> {
+    let (_, encoded) = stream_block(format)
+        .with_context(|| format!("Format {format} doesn't support streaming"))?;
+    let mut buf = vec![0u8; STREAM_CHUNK];
+    let mut pending = Symbols::new(&[], 0, format, lenient);
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        pending.extend(Symbols::new(&buf[..n], pending.end, format, lenient));
+
+        // Hold back the last block, it is the only one allowed to carry padding
+        let ready = pending.data.len().saturating_sub(1) / encoded * encoded;
+        if ready > 0 {
+            if has_padding(format)
+                && let Some(i) = pending.data[..ready].iter().position(|c| *c == b'=')
+            {
+                return Err(anyhow::anyhow!(
+                    "Padding before end of input at offset {}",
+                    pending.offset(i)
+                ));
+            }
+            writer.write_all(&pending.decode_prefix(ready, format, lenient)?)?;
+            pending.drain(ready);
+        }
+    }
+
+    writer.write_all(&pending.decode(format, lenient)?)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Base64 `=` can only end the input. Base32 decodes padded blocks one after another,
+/// so its stream blocks are checked by the decoder itself.
+fn has_padding(format: CodecFormat) -> bool {
+    matches!(
+        format,
+        CodecFormat::Standard
+            | CodecFormat::StandardNoPad
+            | CodecFormat::UrlSafe
+            | CodecFormat::UrlSafeNoPad
+            | CodecFormat::Auto
+    )
+}
+
+/// Insert a newline every `width` characters, and a final one when anything was wrapped
+struct LineWrapWriter<W: Write> {
+    inner: W,
+    width: usize,
+    column: usize,
+}
+
+impl<W: Write> LineWrapWriter<W> {
+    fn new(inner: W, width: usize) -> Self {
+        Self {
+            inner,
+            width,
+            column: 0,
+        }
+    }
+
+    fn finish(mut self) -> io::Result<W> {
+        if self.width > 0 && self.column > 0 {
+            self.inner.write_all(b"\n")?;
+        }
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for LineWrapWriter<W> {
+    fn write(&mut self, mut buf: &[u8]) -> io::Result<usize> {
+        let written = buf.len();
+        if self.width == 0 {
+            self.inner.write_all(buf)?;
+            return Ok(written);
+        }
+
+        while !buf.is_empty() {
+            if self.column == self.width {
+                self.inner.write_all(b"\n")?;
+                self.column = 0;
+            }
+            let n = buf.len().min(self.width - self.column);
+            self.inner.write_all(&buf[..n])?;
+            self.column += n;
+            buf = &buf[n..];
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Encode 4-byte groups as 5 digits, a trailing partial group of n bytes becomes n + 1 digits
+fn base85_encode(data: &[u8], alphabet: &[u8; 85], zero_shortcut: bool) -> String {
+    let mut result = Vec::with_capacity(data.len().div_ceil(4) * 5);
+    for chunk in data.chunks(4) {
+        if zero_shortcut && chunk == [0u8; 4] {
+            result.push(b'z');
+            continue;
+        }
+
+        let mut group = [0u8; 4];
+        group[..chunk.len()].copy_from_slice(chunk);
+        let mut value = u32::from_be_bytes(group);
+        let mut digits = [0u8; 5];
+        for digit in digits.iter_mut().rev() {
+            *digit = alphabet[(value % 85) as usize];
+            value /= 85;
+        }
+        result.extend_from_slice(&digits[..chunk.len() + 1]);
+    }
+
+    String::from_utf8(result).expect("Base85 alphabet is ascii")
+}
+
+fn base85_decode(
+    data: &[u8],
+    alphabet: &[u8; 85],
+    zero_shortcut: bool,
+    offset_of: &dyn Fn(usize) -> usize,
+) -> Result<Vec<u8>> {
+    let mut result = Vec::with_capacity(data.len() / 5 * 4 + 4);
+    let mut group = [0u8; 5];
+    let mut len = 0;
+
+    for (offset, c) in data.iter().enumerate() {
+        if zero_shortcut && *c == b'z' {
+            if len != 0 {
+                return Err(anyhow::anyhow!(
+                    "Invalid 'z' inside group at offset {}",
+                    offset_of(offset)
+                ));
+            }
+            result.extend_from_slice(&[0u8; 4]);
+            continue;
+        }
+
+        let digit = alphabet.iter().position(|a| a == c).with_context(|| {
+            format!(
+                "Invalid base85 character {:?} at offset {}",
+                *c as char,
+                offset_of(offset)
+            )
+        })?;
+        group[len] = digit as u8;
+        len += 1;
+        if len == 5 {
+            result.extend_from_slice(&base85_group(&group, offset_of(offset))?);
+            len = 0;
+        }
+    }
+
+    match len {
+        0 => {}
+        1 => {
+            return Err(anyhow::anyhow!(
+                "Invalid base85 input: dangling final digit"
+            ));
+        }
+        _ => {
+            group[len..].fill(84);
+            let bytes = base85_group(&group, offset_of(data.len()))?;
+            result.extend_from_slice(&bytes[..len - 1]);
+        }
+    }
+
+    Ok(result)
+}
+
+fn base85_group(digits: &[u8; 5], offset: usize) -> Result<[u8; 4]> {
+    let value = digits
+        .iter()
+        .fold(0u64, |acc, digit| acc * 85 + *digit as u64);
+    let value = u32::try_from(value)
+        .map_err(|_| anyhow::anyhow!("Invalid base85 group ending at offset {offset}"))?;
+    Ok(value.to_be_bytes())
+}
+
 #[cfg(test)]
 mod test {
-    use std::fs;
+    use std::{fs, io::Cursor};
 
     use base64::prelude::*;
+
+    use crate::{
+        CodecFormat,
+        process::process_base64::{
+            decode_bytes, decode_stream, encode_bytes, encode_stream, process_base64_decode,
+            process_base64_encode,
+        },
+        utils::TrimFinalNewline,
+    };
+
+    const ALL_FORMATS: [CodecFormat; 11] = [
+        CodecFormat::Standard,
+        CodecFormat::StandardNoPad,
+        CodecFormat::UrlSafe,
+        CodecFormat::UrlSafeNoPad,
+        CodecFormat::Base32,
+        CodecFormat::Base32NoPad,
+        CodecFormat::Base58,
+        CodecFormat::Ascii85,
+        CodecFormat::Z85,
+        CodecFormat::Hex,
+        CodecFormat::Auto,
+    ];
+
     #[test]
     fn process_base64_encode_decode() {
         let buf = fs::read("Cargo.toml").unwrap();
         let base64_content = BASE64_STANDARD.encode(&buf);
         assert_eq!(buf, BASE64_STANDARD.decode(base64_content).unwrap());
     }
+
+    #[test]
+    fn test_codecs_round_trip() {
+        let key = fs::read("fixtures/ed25519.sk").unwrap();
+        let inputs: [&[u8]; 6] = [b"", b"f", b"fo", b"foo", b"\0\0\0\0\xff\xfe", &key];
+        for format in ALL_FORMATS {
+            for input in inputs {
+                let encoded = encode_bytes(input, format);
+                if matches!(format, CodecFormat::Z85) && !input.len().is_multiple_of(4) {
+                    assert!(decode_bytes(encoded.as_bytes(), format, false).is_err());
+                } else {
+                    assert_eq!(
+                        decode_bytes(encoded.as_bytes(), format, false).unwrap(),
+                        input,
+                        "{format} strict"
+                    );
+                }
+                assert_eq!(
+                    decode_bytes(encoded.as_bytes(), format, true).unwrap(),
+                    input,
+                    "{format} lenient"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_codec_known_values() {
+        assert_eq!(encode_bytes(b"hello", CodecFormat::Base32), "NBSWY3DP");
+        assert_eq!(encode_bytes(b"hi", CodecFormat::Base32), "NBUQ====");
+        assert_eq!(encode_bytes(b"hi", CodecFormat::Base32NoPad), "NBUQ");
+        assert_eq!(
+            encode_bytes(b"hello world", CodecFormat::Base58),
+            "StV1DL6CwTryKyV"
+        );
+        assert_eq!(encode_bytes(b"Man ", CodecFormat::Ascii85), "9jqo^");
+        assert_eq!(
+            encode_bytes(
+                &[0x86, 0x4f, 0xd2, 0x6f, 0xb5, 0x59, 0xf7, 0x5b],
+                CodecFormat::Z85
+            ),
+            "HelloWorld"
+        );
+        assert_eq!(encode_bytes(b"\x01\xab", CodecFormat::Hex), "01ab");
+    }
+
+    #[test]
+    fn test_strict_and_lenient_decode() {
+        // Whitespace is the one thing both modes ignore, the CLI decodes wrapped input strictly
+        let wrapped = b"aGVs\nbG8=\n";
+        for lenient in [false, true] {
+            assert_eq!(
+                decode_bytes(wrapped, CodecFormat::Standard, lenient).unwrap(),
+                b"hello"
+            );
+            let mut streamed = Vec::new();
+            decode_stream(&wrapped[..], &mut streamed, CodecFormat::Standard, lenient).unwrap();
+            assert_eq!(streamed, b"hello");
+        }
+        assert_eq!(
+            decode_bytes(b"NBSW Y3DP", CodecFormat::Base32, false).unwrap(),
+            b"hello"
+        );
+        assert!(decode_bytes(b"aGVsbG8", CodecFormat::Standard, false).is_err());
+        assert_eq!(
+            decode_bytes(b"aGVsbG8", CodecFormat::Standard, true).unwrap(),
+            b"hello"
+        );
+        assert!(decode_bytes(b"nbswy3dp", CodecFormat::Base32, false).is_err());
+        assert_eq!(
+            decode_bytes(b"nbsw y3dp", CodecFormat::Base32, true).unwrap(),
+            b"hello"
+        );
+        assert!(decode_bytes(b"0x01:AB", CodecFormat::Hex, false).is_err());
+        assert!(decode_bytes(b"01AB", CodecFormat::Hex, false).is_err());
+        assert!(decode_bytes(b"HelloWorl", CodecFormat::Z85, false).is_err());
+        assert_eq!(
+            decode_bytes(b"HelloWorld", CodecFormat::Z85, false).unwrap(),
+            [0x86, 0x4f, 0xd2, 0x6f, 0xb5, 0x59, 0xf7, 0x5b]
+        );
+        assert_eq!(
+            decode_bytes(b"0x01:AB", CodecFormat::Hex, true).unwrap(),
+            b"\x01\xab"
+        );
+        assert!(decode_bytes(b"<~9jqo^~>", CodecFormat::Ascii85, false).is_err());
+        assert_eq!(
+            decode_bytes(b"<~9jqo^~>", CodecFormat::Ascii85, true).unwrap(),
+            b"Man "
+        );
+    }
+
+    #[test]
+    fn test_stream_round_trip_across_chunks() {
+        // Larger than one stream chunk and not aligned to any block size
+        let data = (0..200_001u32)
+            .map(|i| (i * 31 % 251) as u8)
+            .collect::<Vec<_>>();
+        for format in ALL_FORMATS {
+            if matches!(format, CodecFormat::Base58 | CodecFormat::Ascii85) {
+                assert!(encode_stream(&data[..], Vec::new(), format, 0).is_err());
+                continue;
+            }
+
+            let mut encoded = Vec::new();
+            encode_stream(Cursor::new(&data), &mut encoded, format, 0).unwrap();
+            assert_eq!(encoded, encode_bytes(&data, format).as_bytes(), "{format}");
+
+            let mut wrapped = Vec::new();
+            encode_stream(Cursor::new(&data), &mut wrapped, format, 76).unwrap();
+            assert!(wrapped.split(|c| *c == b'\n').all(|line| line.len() <= 76));
+
+            // The data isn't a multiple of 4 bytes, so z85 ends in a short group
+            let lenient = matches!(format, CodecFormat::Z85);
+            let mut decoded = Vec::new();
+            decode_stream(Cursor::new(&wrapped), &mut decoded, format, lenient).unwrap();
+            assert_eq!(decoded, data, "{format}");
+        }
+    }
+
+    #[test]
+    fn test_stdin_encode_drops_the_final_newline() {
+        // `echo hello | rcli base64 encode`, like the buffered formats and the baseline
+        let mut encoded = Vec::new();
+        let stdin = TrimFinalNewline::new(&b"hello\n"[..]);
+        encode_stream(stdin, &mut encoded, CodecFormat::Standard, 0).unwrap();
+        assert_eq!(encoded, b"aGVsbG8=");
+    }
+
+    #[test]
+    fn test_process_encode_decode_files() {
+        let dir = std::env::temp_dir();
+        let encoded = dir.join(format!("rcli-base64-{}.txt", std::process::id()));
+        let decoded = dir.join(format!("rcli-base64-{}.bin", std::process::id()));
+        let (encoded, decoded) = (encoded.to_str().unwrap(), decoded.to_str().unwrap());
+        let data = fs::read("fixtures/ed25519.sk").unwrap();
+
+        // Streamed and whole-input formats both decode their own wrapped output strictly
+        for format in [CodecFormat::Standard, CodecFormat::Base58] {
+            process_base64_encode("fixtures/ed25519.sk", encoded, format, 16).unwrap();
+            assert!(fs::read(encoded).unwrap().contains(&b'\n'), "{format}");
+            process_base64_decode(encoded, decoded, format, false).unwrap();
+            assert_eq!(fs::read(decoded).unwrap(), data, "{format}");
+        }
+
+        fs::remove_file(encoded).unwrap();
+        fs::remove_file(decoded).unwrap();
+    }
+
+    #[test]
+    fn test_stream_decode_rejects_padding_in_the_middle() {
+        // Concatenated padded blocks are rejected the same way the whole input is
+        for (input, format) in [
+            (&b"aGk=aGk="[..], CodecFormat::Standard),
+            (b"aGk=aGk=", CodecFormat::UrlSafe),
+            (b"aGk=aGk=", CodecFormat::Auto),
+            (b"NBUQ====NBUQ====", CodecFormat::Base32),
+        ] {
+            for lenient in [false, true] {
+                let whole = decode_bytes(input, format, lenient).map_err(|e| e.to_string());
+                let mut decoded = Vec::new();
+                let streamed = decode_stream(input, &mut decoded, format, lenient)
+                    .map(|_| decoded)
+                    .map_err(|e| e.to_string());
+                assert_eq!(whole.is_ok(), streamed.is_ok(), "{format} {lenient}");
+                assert_eq!(whole.ok(), streamed.clone().ok());
+                if !matches!(format, CodecFormat::Base32) {
+                    let err = streamed.unwrap_err();
+                    assert_eq!(err, "Padding before end of input at offset 3");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_error_offsets_point_into_the_input() {
+        // Lenient mode drops whitespace, padding and separators before decoding
+        let cases: [(&[u8], CodecFormat, usize); 5] = [
+            (b"aGVs\nbG*=", CodecFormat::Standard, 7),
+            (b" aG Vs\r\nbG8!", CodecFormat::UrlSafe, 11),
+            (b"nbsw y3d!", CodecFormat::Base32, 8),
+            (b"0x01:ab:c!", CodecFormat::Hex, 9),
+            (b"<~9jq\no^ v~>", CodecFormat::Ascii85, 9),
+        ];
+        for (input, format, offset) in cases {
+            let err = decode_bytes(input, format, true).unwrap_err();
+            assert!(
+                err.to_string().ends_with(&format!("at offset {offset}")),
+                "{format}: {err}"
+            );
+        }
+
+        // Streaming reports the offset in the whole input, not in the current block
+        let data = vec![7u8; 100_000];
+        let mut wrapped = Vec::new();
+        encode_stream(&data[..], &mut wrapped, CodecFormat::Standard, 76).unwrap();
+        let bad = wrapped.len() - 100;
+        for (byte, lenient, message) in [
+            (b'*', true, "Invalid base64 character '*'"),
+            (b'=', false, "Padding before end of input"),
+        ] {
+            let mut input = wrapped.clone();
+            input[bad] = byte;
+            let err = decode_stream(&input[..], Vec::new(), CodecFormat::Standard, lenient)
+                .unwrap_err()
+                .to_string();
+            assert_eq!(err, format!("{message} at offset {bad}"));
+        }
+    }
+
+    #[test]
+    fn test_auto_decode() {
+        let data = b"\xfb\xff\xfehello";
+        for encoded in ["+//+aGVsbG8=", "-__-aGVsbG8", "+/_-\naGVs bG8=\r\n"] {
+            assert_eq!(
+                decode_bytes(encoded.as_bytes(), CodecFormat::Auto, false).unwrap(),
+                data
+            );
+        }
+
+        let err = decode_bytes(b"aGVs\nbG*=", CodecFormat::Auto, false).unwrap_err();
+        assert_eq!(err.to_string(), "Invalid base64 character '*' at offset 7");
+        let err = decode_bytes(b"aGk=aGk=", CodecFormat::Auto, false).unwrap_err();
+        assert!(err.to_string().contains("'a' at offset 4"));
+        let err = decode_bytes(b"aGVsb", CodecFormat::Auto, false).unwrap_err();
+        assert!(err.to_string().contains("'b' at offset 4"));
+        let err = decode_bytes(b"aGVs\tbG9.", CodecFormat::Standard, false).unwrap_err();
+        assert_eq!(err.to_string(), "Invalid base64 character '.' at offset 8");
+    }
 }
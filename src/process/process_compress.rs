@@ -0,0 +1,351 @@
+use std::io::{self, BufReader, Read, Write};
+
+use anyhow::{Context, Result};
+use base64::{Engine, prelude::BASE64_STANDARD, read::DecoderReader, write::EncoderWriter};
+use flate2::{
+    Compression,
+    read::{DeflateDecoder, MultiGzDecoder, ZlibDecoder},
+    write::{DeflateEncoder, GzEncoder, ZlibEncoder},
+};
+
+use crate::{CompressFormat, open_input, open_output, utils::read_head};
+
+const BROTLI_BUFFER: usize = 64 * 1024;
+const BROTLI_WINDOW: i32 = 22;
+
+const GZIP_MAGIC: &[u8] = b"\x1f\x8b";
+const ZSTD_MAGIC: &[u8] = b"\x28\xb5\x2f\xfd";
+/// Base64 of the gzip and zstd magic, so `compress --base64` output is detected too
+const GZIP_BASE64_MAGIC: &[u8] = b"H4sI";
+const ZSTD_BASE64_MAGIC: &[u8] = b"KLUv";
+/// Leading bytes `detect_format` needs to tell every format apart
+const DETECT_LEN: usize = 4;
+
+/// Default level for each format, used when no level is given
+pub fn default_level(format: CompressFormat) -> i32 {
+    match format {
+        CompressFormat::Gzip | CompressFormat::Zlib | CompressFormat::Deflate => 6,
+        CompressFormat::Zstd => 3,
+        CompressFormat::Brotli => 6,
+        CompressFormat::Auto => 0,
+    }
+}
+
+fn check_level(format: CompressFormat, level: i32) -> Result<()> {
+    let range = match format {
+        CompressFormat::Gzip | CompressFormat::Zlib | CompressFormat::Deflate => 0..=9,
+        CompressFormat::Zstd => 1..=22,
+        CompressFormat::Brotli => 0..=11,
+        CompressFormat::Auto => {
+            return Err(anyhow::anyhow!(
+                "Auto format is only supported for decompressing"
+            ));
+        }
+    };
+    if !range.contains(&level) {
+        return Err(anyhow::anyhow!(
+            "Level for {format} must be between {} and {}",
+            range.start(),
+            range.end()
+        ));
+    }
+    Ok(())
+}
+
+/// Compress everything from `reader` into `writer`, returning the writer once the
+/// format's trailer is written so callers can finish any outer encoding
+pub fn compress_stream<W: Write>(
+    mut reader: impl Read,
+    writer: W,
+    format: CompressFormat,
+    level: i32,
+) -> Result<W> {
+    check_level(format, level)?;
+    let writer = match format {
+        CompressFormat::Gzip => {
+            let mut encoder = GzEncoder::new(writer, Compression::new(level as u32));
+            io::copy(&mut reader, &mut encoder)?;
+            encoder.finish()?
+        }
+        CompressFormat::Zlib => {
+            let mut encoder = ZlibEncoder::new(writer, Compression::new(level as u32));
+            io::copy(&mut reader, &mut encoder)?;
+            encoder.finish()?
+        }
+        CompressFormat::Deflate => {
+            let mut encoder = DeflateEncoder::new(writer, Compression::new(level as u32));
+            io::copy(&mut reader, &mut encoder)?;
+            encoder.finish()?
+        }
+        CompressFormat::Zstd => {
+            let mut encoder = zstd::Encoder::new(writer, level)?;
+            io::copy(&mut reader, &mut encoder)?;
+            encoder.finish()?
+        }
+        CompressFormat::Brotli => {
+            // CompressorWriter::into_inner drops the error of the final block
+            let params = brotli::enc::BrotliEncoderParams {
+                quality: level,
+                lgwin: BROTLI_WINDOW,
+                ..Default::default()
+            };
+            let mut writer = writer;
+            brotli::BrotliCompress(&mut reader, &mut writer, &params)?;
+            writer
+        }
+        CompressFormat::Auto => unreachable!("rejected by check_level"),
+    };
+
+    Ok(writer)
+}
+
+/// Pick the format from the leading magic bytes, the bool is true when the
+/// compressed stream is itself base64 encoded
+pub fn detect_format(head: &[u8]) -> Option<(CompressFormat, bool)> {
+    if head.starts_with(GZIP_MAGIC) {
+        Some((CompressFormat::Gzip, false))
+    } else if head.starts_with(ZSTD_MAGIC) {
+        Some((CompressFormat::Zstd, false))
+    } else if is_zlib_header(head) {
+        Some((CompressFormat::Zlib, false))
+    } else if head.starts_with(GZIP_BASE64_MAGIC) {
+        Some((CompressFormat::Gzip, true))
+    } else if head.starts_with(ZSTD_BASE64_MAGIC) {
+        Some((CompressFormat::Zstd, true))
+    } else if head.len() >= 4 {
+        // A zlib header encodes to `e` followed by a letter that depends on the level
+        let decoded = BASE64_STANDARD.decode(&head[..4]).ok()?;
+        is_zlib_header(&decoded).then_some((CompressFormat::Zlib, true))
+    } else {
+        None
+    }
+}
+
+/// Deflate with a window of at most 32K, no preset dictionary and a valid check value
+fn is_zlib_header(head: &[u8]) -> bool {
+    match head {
+        [cmf, flg, ..] => {
+            cmf & 0x0f == 8
+                && cmf >> 4 <= 7
+                && flg & 0x20 == 0
+                && (*cmf as u16 * 256 + *flg as u16).is_multiple_of(31)
+        }
+        _ => false,
+    }
+}
+
+pub fn decompress_stream(
+    reader: impl Read,
+    mut writer: impl Write,
+    format: CompressFormat,
+    base64: bool,
+) -> Result<()> {
+    let (head, reader) = read_head(reader, DETECT_LEN)?;
+    let reader = BufReader::new(reader);
+    let (format, base64) = match format {
+        CompressFormat::Auto => detect_format(&head)
+            .context("Unable to detect the compression format, brotli and deflate need --format")?,
+        format => (format, base64),
+    };
+
+    let reader: Box<dyn Read> = match base64 {
+        true => Box::new(DecoderReader::new(SkipWhitespace(reader), &BASE64_STANDARD)),
+        false => Box::new(reader),
+    };
+    let mut decoder: Box<dyn Read> = match format {
+        CompressFormat::Gzip => Box::new(MultiGzDecoder::new(reader)),
+        CompressFormat::Zlib => Box::new(ZlibDecoder::new(reader)),
+        CompressFormat::Deflate => Box::new(DeflateDecoder::new(reader)),
+        CompressFormat::Zstd => Box::new(zstd::Decoder::new(reader)?),
+        CompressFormat::Brotli => Box::new(brotli::Decompressor::new(reader, BROTLI_BUFFER)),
+        CompressFormat::Auto => unreachable!("resolved above"),
+    };
+    io::copy(&mut decoder, &mut writer).with_context(|| format!("Decompress {format} failed"))?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Drop whitespace so wrapped base64 can be streamed into the decoder
+struct SkipWhitespace<R>(R);
+
+impl<R: Read> Read for SkipWhitespace<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let n = self.0.read(buf)?;
+            if n == 0 {
+                return Ok(0);
+            }
+            let mut kept = 0;
+            for i in 0..n {
+                if !buf[i].is_ascii_whitespace() {
+                    buf[kept] = buf[i];
+                    kept += 1;
+                }
+            }
+            if kept > 0 {
+                return Ok(kept);
+            }
+        }
+    }
+}
+
+pub fn process_compress(
+    input: &str,
+    output: &str,
+    format: CompressFormat,
+    level: Option<i32>,
+    base64: bool,
+) -> Result<()> {
+    let reader = open_input(input)?;
+    let writer = open_output(output)?;
+    let level = level.unwrap_or_else(|| default_level(format));
+    let mut writer = match base64 {
+        true => compress_stream(
+            reader,
+            EncoderWriter::new(writer, &BASE64_STANDARD),
+            format,
+            level,
+        )?
+        .finish()?,
+        false => compress_stream(reader, writer, format, level)?,
+    };
+    writer.flush()?;
+    Ok(())
+}
+
+pub fn process_decompress(
+    input: &str,
+    output: &str,
+    format: CompressFormat,
+    base64: bool,
+) -> Result<()> {
+    let reader = open_input(input)?;
+    let writer = open_output(output)?;
+    decompress_stream(reader, writer, format, base64)
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use base64::{prelude::BASE64_STANDARD, write::EncoderWriter};
+
+    use crate::{
+        CompressFormat,
+        process::process_compress::{
+            compress_stream, decompress_stream, default_level, detect_format,
+        },
+        utils::test::Trickle,
+    };
+
+    const ALL_FORMATS: [CompressFormat; 5] = [
+        CompressFormat::Gzip,
+        CompressFormat::Zlib,
+        CompressFormat::Deflate,
+        CompressFormat::Zstd,
+        CompressFormat::Brotli,
+    ];
+
+    #[test]
+    fn test_compress_round_trip() {
+        let data = fs::read("fixtures/juventus.csv").unwrap();
+        for format in ALL_FORMATS {
+            let compressed =
+                compress_stream(&data[..], Vec::new(), format, default_level(format)).unwrap();
+            assert!(compressed.len() < data.len(), "{format}");
+
+            let mut decompressed = Vec::new();
+            decompress_stream(&compressed[..], &mut decompressed, format, false).unwrap();
+            assert_eq!(decompressed, data, "{format}");
+        }
+
+        assert!(compress_stream(&data[..], Vec::new(), CompressFormat::Gzip, 10).is_err());
+        assert!(compress_stream(&data[..], Vec::new(), CompressFormat::Auto, 1).is_err());
+    }
+
+    #[test]
+    fn test_decompress_auto_detects_format_and_base64() {
+        let data = b"hello hello hello hello".repeat(100);
+        for format in [
+            CompressFormat::Gzip,
+            CompressFormat::Zlib,
+            CompressFormat::Zstd,
+        ] {
+            for level in [1, 9] {
+                let compressed = compress_stream(&data[..], Vec::new(), format, level).unwrap();
+                let (detected, base64) = detect_format(&compressed).unwrap();
+                assert_eq!(detected.to_string(), format.to_string());
+                assert!(!base64);
+
+                let encoded = compress_stream(
+                    &data[..],
+                    EncoderWriter::new(Vec::new(), &BASE64_STANDARD),
+                    format,
+                    level,
+                )
+                .unwrap()
+                .finish()
+                .unwrap();
+                let wrapped = encoded.chunks(76).collect::<Vec<_>>().join(&b'\n').to_vec();
+
+                for input in [compressed, wrapped] {
+                    let mut decompressed = Vec::new();
+                    decompress_stream(&input[..], &mut decompressed, CompressFormat::Auto, false)
+                        .unwrap();
+                    assert_eq!(decompressed, data, "{format} level {level}");
+
+                    // Detection must not depend on the first read returning the whole magic
+                    let mut decompressed = Vec::new();
+                    let reader = Trickle(&input);
+                    decompress_stream(reader, &mut decompressed, CompressFormat::Auto, false)
+                        .unwrap();
+                    assert_eq!(decompressed, data, "{format} level {level} trickled");
+                }
+            }
+        }
+
+        let brotli = compress_stream(&data[..], Vec::new(), CompressFormat::Brotli, 6).unwrap();
+        assert!(decompress_stream(&brotli[..], Vec::new(), CompressFormat::Auto, false).is_err());
+        assert!(
+            decompress_stream(
+                &b"not compressed"[..],
+                Vec::new(),
+                CompressFormat::Gzip,
+                false
+            )
+            .is_err()
+        );
+    }
+
+    /// Accepts `limit` bytes, then fails every write
+    struct FailAfter(usize);
+
+    impl std::io::Write for FailAfter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            if self.0 == 0 {
+                return Err(std::io::Error::other("disk full"));
+            }
+            let n = buf.len().min(self.0);
+            self.0 -= n;
+            Ok(n)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_compress_reports_a_failed_final_write() {
+        let data = fs::read("Cargo.toml").unwrap();
+        for format in ALL_FORMATS {
+            let level = default_level(format);
+            let len = compress_stream(&data[..], Vec::new(), format, level)
+                .unwrap()
+                .len();
+            assert!(compress_stream(&data[..], FailAfter(len), format, level).is_ok());
+            let short = compress_stream(&data[..], FailAfter(len - 1), format, level);
+            assert!(short.is_err(), "{format}");
+        }
+    }
+}
@@ -0,0 +1,344 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use base64::prelude::*;
+use percent_encoding::percent_decode;
+
+use crate::{read_buffer_from_input, write_buffer_to_output};
+
+const DEFAULT_MIME: &str = "application/octet-stream";
+
+/// Leading magic bytes, checked in order so the more specific entries come first
+const MAGIC: &[(&[u8], &str)] = &[
+    (b"\x89PNG\r\n\x1a\n", "image/png"),
+    (b"\xff\xd8\xff", "image/jpeg"),
+    (b"GIF87a", "image/gif"),
+    (b"GIF89a", "image/gif"),
+    (b"%PDF-", "application/pdf"),
+    (b"\x1f\x8b", "application/gzip"),
+    (b"PK\x03\x04", "application/zip"),
+    (b"\x00asm", "application/wasm"),
+    (b"wOFF", "font/woff"),
+    (b"wOF2", "font/woff2"),
+    (b"OggS", "audio/ogg"),
+    (b"ID3", "audio/mpeg"),
+    (b"fLaC", "audio/flac"),
+];
+
+/// ISO base media files start with an ftyp box, its major brand names the actual format
+const FTYP_BRANDS: &[(&[u8; 4], &str)] = &[
+    (b"avif", "image/avif"),
+    (b"avis", "image/avif"),
+    (b"heic", "image/heic"),
+    (b"heix", "image/heic"),
+    (b"heim", "image/heic"),
+    (b"heis", "image/heic"),
+    (b"mif1", "image/heif"),
+    (b"msf1", "image/heif"),
+    (b"qt  ", "video/quicktime"),
+    (b"M4A ", "audio/mp4"),
+    (b"M4V ", "video/mp4"),
+    (b"isom", "video/mp4"),
+    (b"iso2", "video/mp4"),
+    (b"mp41", "video/mp4"),
+    (b"mp42", "video/mp4"),
+    (b"avc1", "video/mp4"),
+    (b"dash", "video/mp4"),
+    (b"3gp4", "video/3gpp"),
+    (b"3gp5", "video/3gpp"),
+];
+
+/// Non-standard names in common use, mapped to the registered type
+const ALIASES: &[(&str, &str)] = &[
+    ("image/jpg", "image/jpeg"),
+    ("image/pjpeg", "image/jpeg"),
+    ("audio/mp3", "audio/mpeg"),
+    ("audio/x-mp3", "audio/mpeg"),
+    ("audio/x-wav", "audio/wav"),
+    ("audio/wave", "audio/wav"),
+    ("audio/x-flac", "audio/flac"),
+    ("audio/x-m4a", "audio/mp4"),
+    ("application/x-gzip", "application/gzip"),
+    ("application/x-zip-compressed", "application/zip"),
+    ("application/x-pdf", "application/pdf"),
+    ("application/font-woff", "font/woff"),
+];
+
+const EXTENSIONS: &[(&str, &str)] = &[
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("webp", "image/webp"),
+    ("avif", "image/avif"),
+    ("heic", "image/heic"),
+    ("heif", "image/heif"),
+    ("svg", "image/svg+xml"),
+    ("ico", "image/x-icon"),
+    ("bmp", "image/bmp"),
+    ("pdf", "application/pdf"),
+    ("gz", "application/gzip"),
+    ("zip", "application/zip"),
+    ("wasm", "application/wasm"),
+    ("json", "application/json"),
+    ("js", "text/javascript"),
+    ("css", "text/css"),
+    ("html", "text/html"),
+    ("htm", "text/html"),
+    ("txt", "text/plain"),
+    ("csv", "text/csv"),
+    ("xml", "application/xml"),
+    ("woff", "font/woff"),
+    ("woff2", "font/woff2"),
+    ("ttf", "font/ttf"),
+    ("otf", "font/otf"),
+    ("mp3", "audio/mpeg"),
+    ("ogg", "audio/ogg"),
+    ("flac", "audio/flac"),
+    ("wav", "audio/wav"),
+    ("m4a", "audio/mp4"),
+    ("mp4", "video/mp4"),
+    ("mov", "video/quicktime"),
+    ("webm", "video/webm"),
+];
+
+/// Mime type from the content's magic bytes, None when nothing matches
+pub fn sniff_mime_bytes(data: &[u8]) -> Option<&'static str> {
+    // RIFF containers share a prefix, the format id follows the chunk size
+    if data.len() >= 12 && data.starts_with(b"RIFF") {
+        return match &data[8..12] {
+            b"WEBP" => Some("image/webp"),
+            b"WAVE" => Some("audio/wav"),
+            _ => None,
+        };
+    }
+    if data.len() >= 12 && &data[4..8] == b"ftyp" {
+        return FTYP_BRANDS
+            .iter()
+            .find(|(brand, _)| &data[8..12] == *brand)
+            .map(|(_, mime)| *mime);
+    }
+
+    MAGIC
+        .iter()
+        .find(|(magic, _)| data.starts_with(magic))
+        .map(|(_, mime)| *mime)
+}
+
+/// Magic bytes win over the file extension, unknown content falls back to octet-stream
+pub fn sniff_mime(data: &[u8], path: &str) -> &'static str {
+    sniff_mime_bytes(data)
+        .or_else(|| {
+            let ext = Path::new(path).extension()?.to_str()?.to_ascii_lowercase();
+            EXTENSIONS
+                .iter()
+                .find(|(e, _)| *e == ext)
+                .map(|(_, mime)| *mime)
+        })
+        .unwrap_or(DEFAULT_MIME)
+}
+
+pub fn encode_data_uri(data: &[u8], mime: &str) -> String {
+    format!("data:{};base64,{}", mime, BASE64_STANDARD.encode(data))
+}
+
+/// Split a data URI into its media type and decoded payload. Payloads without `;base64`
+/// are percent-decoded, and the media type defaults to text/plain as in RFC 2397.
+pub fn parse_data_uri(uri: &str) -> Result<(String, Vec<u8>)> {
+    let uri = uri.trim();
+    let rest = uri
+        .get(..5)
+        .filter(|scheme| scheme.eq_ignore_ascii_case("data:"))
+        .map(|_| &uri[5..])
+        .context("Data URI must start with data:")?;
+    let (header, payload) = rest
+        .split_once(',')
+        .context("Data URI is missing the ',' before the payload")?;
+
+    let (media_type, base64) = match header.strip_suffix(";base64") {
+        Some(media_type) => (media_type, true),
+        None => (header, false),
+    };
+    let media_type = match media_type {
+        "" => "text/plain;charset=US-ASCII".to_string(),
+        media_type if media_type.starts_with(';') => format!("text/plain{media_type}"),
+        media_type => media_type.to_string(),
+    };
+    validate_mime(&media_type)?;
+
+    let data = if base64 {
+        let payload = percent_decode(payload.as_bytes())
+            .filter(|c| !c.is_ascii_whitespace())
+            .collect::<Vec<_>>();
+        BASE64_STANDARD
+            .decode(&payload)
+            .context("Invalid base64 payload in data URI")?
+    } else {
+        percent_decode(payload.as_bytes()).collect()
+    };
+
+    Ok((media_type, data))
+}
+
+/// Check `type/subtype` followed by optional `;key=value` parameters
+fn validate_mime(media_type: &str) -> Result<()> {
+    let mut parts = media_type.split(';');
+    let essence = parts.next().unwrap_or_default().trim();
+    let valid_token = |s: &str| {
+        !s.is_empty()
+            && s.bytes()
+                .all(|c| c.is_ascii_alphanumeric() || b"!#$&-^_.+".contains(&c))
+    };
+    match essence.split_once('/') {
+        Some((kind, subtype)) if valid_token(kind) && valid_token(subtype) => {}
+        _ => return Err(anyhow::anyhow!("Invalid mime type: {}", essence)),
+    }
+    for param in parts {
+        match param.split_once('=') {
+            Some((key, value)) if valid_token(key.trim()) && !value.is_empty() => {}
+            _ => return Err(anyhow::anyhow!("Invalid mime parameter: {}", param)),
+        }
+    }
+
+    Ok(())
+}
+
+/// Lowercase `type/subtype` without parameters, with aliases resolved
+fn mime_essence(media_type: &str) -> String {
+    let essence = media_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    match ALIASES.iter().find(|(alias, _)| *alias == essence) {
+        Some((_, mime)) => mime.to_string(),
+        None => essence,
+    }
+}
+
+/// Reject content whose magic bytes say something else than the declared type. A generic
+/// declared type says nothing about the content, and zip is the container of many formats.
+fn check_declared_mime(declared: &str, data: &[u8]) -> Result<()> {
+    let declared = mime_essence(declared);
+    match sniff_mime_bytes(data) {
+        Some(sniffed)
+            if sniffed != declared && declared != DEFAULT_MIME && sniffed != "application/zip" =>
+        {
+            Err(anyhow::anyhow!(
+                "Declared mime type {} doesn't match content {}",
+                declared,
+                sniffed
+            ))
+        }
+        _ => Ok(()),
+    }
+}
+
+pub fn process_data_uri_encode(input: &str, mime: Option<&str>) -> Result<String> {
+    let data = read_buffer_from_input(input)?;
+    let mime = match mime {
+        Some(mime) => {
+            validate_mime(mime)?;
+            mime
+        }
+        None => sniff_mime(&data, input),
+    };
+    Ok(encode_data_uri(&data, mime))
+}
+
+/// Decode the payload after checking the declared type is well formed, matches the
+/// content's magic bytes when they are recognized, and matches `expect` when given
+pub fn process_data_uri_decode(input: &str, output: &str, expect: Option<&str>) -> Result<()> {
+    let buf = read_buffer_from_input(input)?;
+    let uri = String::from_utf8(buf).context("Data URI must be utf8 text")?;
+    let (media_type, data) = parse_data_uri(&uri)?;
+    check_declared_mime(&media_type, &data)?;
+    if let Some(expect) = expect
+        && mime_essence(expect) != mime_essence(&media_type)
+    {
+        return Err(anyhow::anyhow!(
+            "Expected mime type {} but data URI declares {}",
+            expect,
+            mime_essence(&media_type)
+        ));
+    }
+
+    eprintln!("Mime type: {}", media_type);
+    write_buffer_to_output(output, &data)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::process::process_data_uri::{
+        check_declared_mime, encode_data_uri, parse_data_uri, sniff_mime, sniff_mime_bytes,
+    };
+
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+
+    #[test]
+    fn test_sniff_mime() {
+        assert_eq!(sniff_mime(PNG, "logo.bin"), "image/png");
+        assert_eq!(sniff_mime(b"body {}", "style.CSS"), "text/css");
+        assert_eq!(sniff_mime(b"RIFF\0\0\0\0WEBPVP8 ", "-"), "image/webp");
+        assert_eq!(sniff_mime(b"plain", "-"), "application/octet-stream");
+        assert_eq!(sniff_mime_bytes(b"{}"), None);
+        // Too common at the start of binary data to mean an icon
+        assert_eq!(sniff_mime_bytes(b"\0\0\x01\0\x01\0"), None);
+        assert_eq!(
+            sniff_mime(b"\0\0\x01\0\x01\0", "favicon.ico"),
+            "image/x-icon"
+        );
+    }
+
+    #[test]
+    fn test_sniff_ftyp_brand() {
+        let ftyp = |brand: &[u8; 4]| [b"\0\0\0\x1cftyp".as_slice(), brand, b"\0\0\0\0"].concat();
+        assert_eq!(sniff_mime_bytes(&ftyp(b"avif")), Some("image/avif"));
+        assert_eq!(sniff_mime_bytes(&ftyp(b"heic")), Some("image/heic"));
+        assert_eq!(sniff_mime_bytes(&ftyp(b"qt  ")), Some("video/quicktime"));
+        assert_eq!(sniff_mime_bytes(&ftyp(b"M4A ")), Some("audio/mp4"));
+        assert_eq!(sniff_mime_bytes(&ftyp(b"isom")), Some("video/mp4"));
+        assert_eq!(sniff_mime_bytes(&ftyp(b"crx ")), None);
+    }
+
+    #[test]
+    fn test_check_declared_mime() {
+        assert!(check_declared_mime("image/png", PNG).is_ok());
+        assert!(check_declared_mime("IMAGE/PNG; name=logo", PNG).is_ok());
+        assert!(check_declared_mime("image/jpeg", PNG).is_err());
+
+        // Aliases resolve to the registered type the sniffer reports
+        assert!(check_declared_mime("image/jpg", b"\xff\xd8\xff\xe0").is_ok());
+        assert!(check_declared_mime("audio/mp3", b"ID3\x04\0").is_ok());
+        assert!(check_declared_mime("application/x-gzip", b"\x1f\x8b\x08").is_ok());
+
+        // Generic types and zip containers can't be contradicted by magic bytes
+        assert!(check_declared_mime("application/octet-stream", PNG).is_ok());
+        let docx = "application/vnd.openxmlformats-officedocument.wordprocessingml.document";
+        assert!(check_declared_mime(docx, b"PK\x03\x04").is_ok());
+        assert!(check_declared_mime("text/plain", b"anything").is_ok());
+    }
+
+    #[test]
+    fn test_data_uri_round_trip() {
+        let uri = encode_data_uri(PNG, "image/png");
+        assert!(uri.starts_with("data:image/png;base64,iVBORw0KGgo"));
+        let (mime, data) = parse_data_uri(&uri).unwrap();
+        assert_eq!(mime, "image/png");
+        assert_eq!(data, PNG);
+
+        let (mime, data) = parse_data_uri("data:,Hello%2C%20World%21").unwrap();
+        assert_eq!(mime, "text/plain;charset=US-ASCII");
+        assert_eq!(data, b"Hello, World!");
+
+        let (mime, data) = parse_data_uri("data:text/html;charset=utf-8;base64,PGI+").unwrap();
+        assert_eq!(mime, "text/html;charset=utf-8");
+        assert_eq!(data, b"<b>");
+
+        assert!(parse_data_uri("http://example.com").is_err());
+        assert!(parse_data_uri("data:image/png;base64").is_err());
+        assert!(parse_data_uri("data:image;base64,AA==").is_err());
+        assert!(parse_data_uri("data:text/plain;charset,AA").is_err());
+    }
+}
@@ -0,0 +1,333 @@
+use anyhow::{Context, Result};
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC, percent_decode, percent_encode};
+
+use crate::{EscapeFormat, read_buffer_from_input, write_buffer_to_output};
+
+/// RFC 3986 unreserved characters are never encoded
+const UNRESERVED: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+/// Sub-delims allowed in most components: ! $ & ' ( ) * + , ; =
+const SUB_DELIMS: &AsciiSet = &UNRESERVED
+    .remove(b'!')
+    .remove(b'$')
+    .remove(b'&')
+    .remove(b'\'')
+    .remove(b'(')
+    .remove(b')')
+    .remove(b'*')
+    .remove(b'+')
+    .remove(b',')
+    .remove(b';')
+    .remove(b'=');
+const USERINFO: &AsciiSet = &SUB_DELIMS.remove(b':');
+const PATH: &AsciiSet = &SUB_DELIMS.remove(b':').remove(b'@').remove(b'/');
+/// Query values keep `&`, `=` and `+` encoded so they can't split or change the pair
+const QUERY: &AsciiSet = &PATH.remove(b'?').add(b'&').add(b'=').add(b'+');
+const FRAGMENT: &AsciiSet = &PATH.remove(b'?');
+/// The WHATWG urlencoded set, space is handled separately as `+`
+const FORM: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'*')
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_');
+
+const HTML_ENTITIES: &[(&str, char)] = &[
+    ("amp", '&'),
+    ("lt", '<'),
+    ("gt", '>'),
+    ("quot", '"'),
+    ("apos", '\''),
+    ("nbsp", '\u{a0}'),
+    ("copy", '\u{a9}'),
+    ("reg", '\u{ae}'),
+    ("trade", '\u{2122}'),
+    ("hellip", '\u{2026}'),
+    ("mdash", '\u{2014}'),
+    ("ndash", '\u{2013}'),
+    ("laquo", '\u{ab}'),
+    ("raquo", '\u{bb}'),
+    ("euro", '\u{20ac}'),
+];
+
+pub fn escape_bytes(data: &[u8], format: EscapeFormat) -> Result<String> {
+    let escaped = match format {
+        EscapeFormat::Url => percent_encode(data, UNRESERVED).to_string(),
+        EscapeFormat::UrlPath => percent_encode(data, PATH).to_string(),
+        EscapeFormat::UrlQuery => percent_encode(data, QUERY).to_string(),
+        EscapeFormat::UrlFragment => percent_encode(data, FRAGMENT).to_string(),
+        EscapeFormat::UrlUserinfo => percent_encode(data, USERINFO).to_string(),
+        EscapeFormat::Form => data
+            .split(|c| *c == b' ')
+            .map(|part| percent_encode(part, FORM).to_string())
+            .collect::<Vec<_>>()
+            .join("+"),
+        EscapeFormat::Html => escape_html(utf8(data)?),
+        EscapeFormat::Json => {
+            let quoted = serde_json::to_string(utf8(data)?)?;
+            quoted[1..quoted.len() - 1].to_string()
+        }
+        EscapeFormat::Rust => utf8(data)?.escape_debug().to_string(),
+    };
+
+    Ok(escaped)
+}
+
+/// Surrounding quotes are optional for json and rust literals
+pub fn unescape_bytes(data: &[u8], format: EscapeFormat) -> Result<Vec<u8>> {
+    let unescaped = match format {
+        EscapeFormat::Url
+        | EscapeFormat::UrlPath
+        | EscapeFormat::UrlQuery
+        | EscapeFormat::UrlFragment
+        | EscapeFormat::UrlUserinfo => {
+            check_percent_escapes(data)?;
+            percent_decode(data).collect()
+        }
+        EscapeFormat::Form => {
+            check_percent_escapes(data)?;
+            let data = data
+                .iter()
+                .map(|c| if *c == b'+' { b' ' } else { *c })
+                .collect::<Vec<_>>();
+            percent_decode(&data).collect()
+        }
+        EscapeFormat::Html => unescape_html(utf8(data)?).into_bytes(),
+        EscapeFormat::Json => {
+            let s = utf8(data)?;
+            let s = strip_quotes(s, '"');
+            serde_json::from_str::<String>(&format!("\"{s}\""))
+                .context("Invalid json string escape")?
+                .into_bytes()
+        }
+        EscapeFormat::Rust => unescape_rust(strip_quotes(utf8(data)?, '"'))?.into_bytes(),
+    };
+
+    Ok(unescaped)
+}
+
+fn utf8(data: &[u8]) -> Result<&str> {
+    std::str::from_utf8(data).context("Input must be utf8 text for this format")
+}
+
+fn strip_quotes(s: &str, quote: char) -> &str {
+    s.strip_prefix(quote)
+        .and_then(|s| s.strip_suffix(quote))
+        .unwrap_or(s)
+}
+
+/// A `%` must start a two digit hex escape, percent_decode would silently keep it
+fn check_percent_escapes(data: &[u8]) -> Result<()> {
+    for (offset, c) in data.iter().enumerate() {
+        if *c == b'%'
+            && !data
+                .get(offset + 1..offset + 3)
+                .is_some_and(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+        {
+            return Err(anyhow::anyhow!("Invalid percent escape at offset {offset}"));
+        }
+    }
+    Ok(())
+}
+
+fn escape_html(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#x27;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Decode numeric references and common named entities, anything unknown is kept as is
+fn unescape_html(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find('&') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let decoded = rest.find(';').and_then(|end| {
+            let entity = &rest[1..end];
+            let c = match entity.strip_prefix('#') {
+                Some(num) => match num.strip_prefix(['x', 'X']) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                    None => num.parse().ok(),
+                }
+                .and_then(char::from_u32),
+                None => HTML_ENTITIES
+                    .iter()
+                    .find(|(name, _)| *name == entity)
+                    .map(|(_, c)| *c),
+            }?;
+            Some((c, end + 1))
+        });
+        match decoded {
+            Some((c, len)) => {
+                result.push(c);
+                rest = &rest[len..];
+            }
+            None => {
+                result.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Rust string literal escapes: \n \r \t \\ \0 \' \" \xHH \u{H..} and line continuations
+fn unescape_rust(s: &str) -> Result<String> {
+    let mut result = String::with_capacity(s.len());
+    let mut chars = s.char_indices().peekable();
+    while let Some((offset, c)) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+
+        let invalid = || anyhow::anyhow!("Invalid rust escape at offset {offset}");
+        match chars.next().map(|(_, c)| c).ok_or_else(invalid)? {
+            'n' => result.push('\n'),
+            'r' => result.push('\r'),
+            't' => result.push('\t'),
+            '\\' => result.push('\\'),
+            '0' => result.push('\0'),
+            '\'' => result.push('\''),
+            '"' => result.push('"'),
+            'x' => {
+                let hex = [chars.next(), chars.next()]
+                    .iter()
+                    .map(|c| c.map(|(_, c)| c).ok_or_else(invalid))
+                    .collect::<Result<String>>()?;
+                let value = u8::from_str_radix(&hex, 16).map_err(|_| invalid())?;
+                if value > 0x7f {
+                    return Err(invalid());
+                }
+                result.push(value as char);
+            }
+            'u' => {
+                if chars.next().map(|(_, c)| c) != Some('{') {
+                    return Err(invalid());
+                }
+                let mut hex = String::new();
+                loop {
+                    match chars.next().map(|(_, c)| c).ok_or_else(invalid)? {
+                        '}' => break,
+                        '_' => {}
+                        c => hex.push(c),
+                    }
+                }
+                let c = u32::from_str_radix(&hex, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or_else(invalid)?;
+                result.push(c);
+            }
+            '\n' => while chars.next_if(|(_, c)| c.is_whitespace()).is_some() {},
+            _ => return Err(invalid()),
+        }
+    }
+
+    Ok(result)
+}
+
+pub fn process_escape(input: &str, output: &str, format: EscapeFormat) -> Result<()> {
+    let buf = read_buffer_from_input(input)?;
+    let escaped = escape_bytes(&buf, format)?;
+    write_buffer_to_output(output, escaped.as_bytes())
+}
+
+pub fn process_unescape(input: &str, output: &str, format: EscapeFormat) -> Result<()> {
+    let buf = read_buffer_from_input(input)?;
+    let unescaped =
+        unescape_bytes(&buf, format).with_context(|| format!("Unescape input {format} failed"))?;
+    write_buffer_to_output(output, &unescaped)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        EscapeFormat,
+        process::process_escape::{escape_bytes, unescape_bytes},
+    };
+
+    const ALL_FORMATS: [EscapeFormat; 9] = [
+        EscapeFormat::Url,
+        EscapeFormat::UrlPath,
+        EscapeFormat::UrlQuery,
+        EscapeFormat::UrlFragment,
+        EscapeFormat::UrlUserinfo,
+        EscapeFormat::Form,
+        EscapeFormat::Html,
+        EscapeFormat::Json,
+        EscapeFormat::Rust,
+    ];
+
+    #[test]
+    fn test_escape_round_trip() {
+        let input = "a b&c=d/e?f#g+h'i\"j<k>\\l\n\tm:n@o~p%q 日本 😀\0";
+        for format in ALL_FORMATS {
+            let escaped = escape_bytes(input.as_bytes(), format).unwrap();
+            assert_eq!(
+                unescape_bytes(escaped.as_bytes(), format).unwrap(),
+                input.as_bytes(),
+                "{format}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_escape_known_values() {
+        let escape = |s: &str, format| escape_bytes(s.as_bytes(), format).unwrap();
+        assert_eq!(escape("a b/c?d=e", EscapeFormat::Url), "a%20b%2Fc%3Fd%3De");
+        assert_eq!(escape("a b/c:@d?", EscapeFormat::UrlPath), "a%20b/c:@d%3F");
+        assert_eq!(
+            escape("a=1&b+c/?", EscapeFormat::UrlQuery),
+            "a%3D1%26b%2Bc/?"
+        );
+        assert_eq!(escape("a b+c*", EscapeFormat::Form), "a+b%2Bc*");
+        assert_eq!(
+            escape("<a href=\"x\">'&'</a>", EscapeFormat::Html),
+            "&lt;a href=&quot;x&quot;&gt;&#x27;&amp;&#x27;&lt;/a&gt;"
+        );
+        assert_eq!(
+            escape("a\"b\n\u{1}", EscapeFormat::Json),
+            "a\\\"b\\n\\u0001"
+        );
+        assert_eq!(escape("a\"b\n\u{1}", EscapeFormat::Rust), "a\\\"b\\n\\u{1}");
+    }
+
+    #[test]
+    fn test_unescape_variants() {
+        let unescape = |s: &str, format| unescape_bytes(s.as_bytes(), format);
+        assert_eq!(
+            unescape("&copy; &#169; &#xA9; &bogus; & x", EscapeFormat::Html).unwrap(),
+            "© © © &bogus; & x".as_bytes()
+        );
+        assert_eq!(
+            unescape("\"caf\\u00e9\"", EscapeFormat::Json).unwrap(),
+            "café".as_bytes()
+        );
+        assert_eq!(
+            unescape("a\\x41\\u{1F600}\\\n    b", EscapeFormat::Rust).unwrap(),
+            "aA😀b".as_bytes()
+        );
+        assert_eq!(
+            unescape("%e6%97%a5", EscapeFormat::Url).unwrap(),
+            "日".as_bytes()
+        );
+        assert!(unescape("100%", EscapeFormat::Url).is_err());
+        assert!(unescape("\\q", EscapeFormat::Rust).is_err());
+        assert!(unescape("\\xff", EscapeFormat::Rust).is_err());
+        assert!(unescape("a\"b", EscapeFormat::Json).is_err());
+    }
+}
@@ -1,10 +1,148 @@
+use std::{f64::consts::LOG2_10, fs, path::Path};
+
+use anyhow::Context;
 use rand::seq::{IndexedRandom, SliceRandom};
+use serde::Deserialize;
 use zxcvbn::{Score, zxcvbn};
 
+use crate::{CharClass, PwnedDb};
+
 const NUMBER: &[u8] = b"0123456789";
 const LOWER: &[u8] = b"abcdefghijklmnopqrstuvwxyz";
 const UPPER: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ";
 const SYMBOL: &[u8] = b"!@#$%^&*";
+const DEFAULT_MAX_ATTEMPTS: usize = 100;
+const DERIVE_CONTEXT: &str = "rcli 2025-01 genpass derive v1";
+
+/// Rules a generated password must satisfy, loadable from a toml or yaml file
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PasswordPolicy {
+    pub min_score: Option<u8>,
+    pub min_entropy_bits: Option<f64>,
+    pub required_classes: Vec<CharClass>,
+    pub banned_substrings: Vec<String>,
+    /// Local Pwned Passwords SHA-1 dump, breached passwords are rejected
+    pub pwned_db: Option<String>,
+    pub max_attempts: Option<usize>,
+}
+
+impl PasswordPolicy {
+    pub fn load(path: &str) -> anyhow::Result<Self> {
+        let content =
+            fs::read_to_string(path).with_context(|| format!("Read policy file {path} failed"))?;
+        let extension = Path::new(path).extension().and_then(|ext| ext.to_str());
+        let policy: Self = match extension {
+            Some("toml") => toml::from_str(&content).context("Parse toml policy failed")?,
+            Some("yaml" | "yml") => {
+                serde_yaml::from_str(&content).context("Parse yaml policy failed")?
+            }
+            _ => return Err(anyhow::anyhow!("Policy file must be .toml, .yaml or .yml")),
+        };
+
+        policy.validated()
+    }
+
+    fn validated(self) -> anyhow::Result<Self> {
+        if let Some(min_score) = self.min_score
+            && min_score > 4
+        {
+            return Err(anyhow::anyhow!(
+                "min_score must be between 0 and 4, got {}",
+                min_score
+            ));
+        }
+        Ok(self)
+    }
+
+    /// Every required class takes at least one character
+    pub fn check_length(&self, length: usize) -> anyhow::Result<()> {
+        let required = [
+            CharClass::Number,
+            CharClass::Lower,
+            CharClass::Upper,
+            CharClass::Symbol,
+        ]
+        .iter()
+        .filter(|class| self.required_classes.contains(class))
+        .count();
+        if length < required {
+            return Err(anyhow::anyhow!(
+                "Password length {} is shorter than the {} required character classes",
+                length,
+                required
+            ));
+        }
+        Ok(())
+    }
+
+    /// Open the breached passwords database once for many checks
+    pub fn open_pwned_db(&self) -> anyhow::Result<Option<PwnedDb>> {
+        self.pwned_db.as_deref().map(PwnedDb::open).transpose()
+    }
+
+    pub fn check(&self, password: &str) -> anyhow::Result<()> {
+        self.check_with(password, self.open_pwned_db()?.as_mut())
+    }
+
+    /// Check against the policy, using `pwned_db` as opened by `open_pwned_db`
+    pub fn check_with(&self, password: &str, pwned_db: Option<&mut PwnedDb>) -> anyhow::Result<()> {
+        for class in &self.required_classes {
+            if !password.bytes().any(|c| class_chars(*class).contains(&c)) {
+                return Err(anyhow::anyhow!("Missing required {} character", class));
+            }
+        }
+
+        let lower = password.to_lowercase();
+        if let Some(banned) = self
+            .banned_substrings
+            .iter()
+            .find(|banned| lower.contains(&banned.to_lowercase()))
+        {
+            return Err(anyhow::anyhow!("Contains banned substring {}", banned));
+        }
+
+        if let Some(db) = pwned_db {
+            let count = db.lookup(password)?;
+            if count > 0 {
+                return Err(anyhow::anyhow!(
+                    "Found {} times in breached passwords",
+                    count
+                ));
+            }
+        }
+
+        if self.min_score.is_none() && self.min_entropy_bits.is_none() {
+            return Ok(());
+        }
+
+        let estimate = zxcvbn(password, &[]);
+        let score: u8 = estimate.score().into();
+        if let Some(min_score) = self.min_score
+            && score < min_score
+        {
+            return Err(anyhow::anyhow!(
+                "Score {} is lower than {}",
+                score,
+                min_score
+            ));
+        }
+
+        // Estimated from zxcvbn guesses, not from the charset size
+        let bits = estimate.guesses_log10() * LOG2_10;
+        if let Some(min_bits) = self.min_entropy_bits
+            && bits < min_bits
+        {
+            return Err(anyhow::anyhow!(
+                "Entropy {:.1} bits is lower than {:.1}",
+                bits,
+                min_bits
+            ));
+        }
+
+        Ok(())
+    }
+}
 
 pub fn process_gen_pass(
     length: usize,
@@ -13,6 +151,22 @@ pub fn process_gen_pass(
     upper: bool,
     symbol: bool,
 ) -> anyhow::Result<String> {
+    let classes = [number, lower, upper, symbol]
+        .iter()
+        .filter(|c| **c)
+        .count();
+    if classes == 0 {
+        return Err(anyhow::anyhow!(
+            "At least one character class must be enabled"
+        ));
+    }
+    if length < classes {
+        return Err(anyhow::anyhow!(
+            "Password length must be at least {}",
+            classes
+        ));
+    }
+
     let mut chars = Vec::with_capacity(96);
     let mut result = Vec::with_capacity(length);
     let mut rng = rand::rng();
@@ -48,10 +202,146 @@ pub fn process_gen_pass(
     Ok(password)
 }
 
+fn class_chars(class: CharClass) -> &'static [u8] {
+    match class {
+        CharClass::Number => NUMBER,
+        CharClass::Lower => LOWER,
+        CharClass::Upper => UPPER,
+        CharClass::Symbol => SYMBOL,
+    }
+}
+
+/// Regenerate until the password satisfies the policy or attempts run out
+pub fn process_gen_pass_with_policy(
+    length: usize,
+    number: bool,
+    lower: bool,
+    upper: bool,
+    symbol: bool,
+    policy: &PasswordPolicy,
+) -> anyhow::Result<String> {
+    for class in &policy.required_classes {
+        let enabled = match class {
+            CharClass::Number => number,
+            CharClass::Lower => lower,
+            CharClass::Upper => upper,
+            CharClass::Symbol => symbol,
+        };
+        if !enabled {
+            return Err(anyhow::anyhow!(
+                "Policy requires {} characters but they are disabled",
+                class
+            ));
+        }
+    }
+
+    policy.check_length(length)?;
+
+    let attempts = policy.max_attempts.unwrap_or(DEFAULT_MAX_ATTEMPTS);
+    let mut pwned_db = policy.open_pwned_db()?;
+    let mut violation = anyhow::anyhow!("Max attempts must be greater than 0");
+    for _ in 0..attempts {
+        let password = process_gen_pass(length, number, lower, upper, symbol)?;
+        match policy.check_with(&password, pwned_db.as_mut()) {
+            Ok(()) => return Ok(password),
+            Err(e) => violation = e,
+        }
+    }
+
+    Err(violation.context(format!(
+        "Password policy can't be met after {} attempts",
+        attempts
+    )))
+}
+
+/// Byte stream from a blake3 xof, sampled without relying on rand's algorithms
+/// so derived passwords stay stable across dependency upgrades
+struct DeriveStream(blake3::OutputReader);
+
+impl DeriveStream {
+    fn below(&mut self, n: usize) -> usize {
+        let n = n as u32;
+        let zone = u32::MAX - u32::MAX % n;
+        loop {
+            let mut buf = [0u8; 4];
+            self.0.fill(&mut buf);
+            let value = u32::from_le_bytes(buf);
+            if value < zone {
+                return (value % n) as usize;
+            }
+        }
+    }
+
+    fn choose(&mut self, chars: &[u8]) -> u8 {
+        chars[self.below(chars.len())]
+    }
+}
+
+/// Derive a site password from a master secret, same inputs give the same password
+#[allow(clippy::too_many_arguments)]
+pub fn process_derive_pass(
+    master: &str,
+    site: &str,
+    login: &str,
+    counter: u32,
+    length: usize,
+    number: bool,
+    lower: bool,
+    upper: bool,
+    symbol: bool,
+) -> anyhow::Result<String> {
+    let classes = [
+        (number, NUMBER),
+        (lower, LOWER),
+        (upper, UPPER),
+        (symbol, SYMBOL),
+    ]
+    .into_iter()
+    .filter_map(|(enabled, chars)| enabled.then_some(chars))
+    .collect::<Vec<_>>();
+    if classes.is_empty() {
+        return Err(anyhow::anyhow!(
+            "At least one character class must be enabled"
+        ));
+    }
+    if length < classes.len() {
+        return Err(anyhow::anyhow!(
+            "Password length must be at least {}",
+            classes.len()
+        ));
+    }
+
+    let key = blake3::derive_key(DERIVE_CONTEXT, master.as_bytes());
+    let mut hasher = blake3::Hasher::new_keyed(&key);
+    // Length prefix each field so ("ab", "c") and ("a", "bc") don't collide
+    for field in [site.as_bytes(), login.as_bytes()] {
+        hasher.update(&(field.len() as u64).to_le_bytes());
+        hasher.update(field);
+    }
+    hasher.update(&counter.to_le_bytes());
+    hasher.update(&(length as u64).to_le_bytes());
+    hasher.update(&[number as u8, lower as u8, upper as u8, symbol as u8]);
+    let mut stream = DeriveStream(hasher.finalize_xof());
+
+    let chars = classes.concat();
+    let mut result = classes
+        .iter()
+        .map(|class| stream.choose(class))
+        .collect::<Vec<_>>();
+    while result.len() < length {
+        result.push(stream.choose(&chars));
+    }
+    for i in (1..result.len()).rev() {
+        let j = stream.below(i + 1);
+        result.swap(i, j);
+    }
+
+    Ok(String::from_utf8(result)?)
+}
+
 pub fn check_password_strength(password: &str) {
     let estimate = zxcvbn(password, &[]);
 
-    eprintln!("Password: {}", password);
     eprintln!("Score: {}/4", estimate.score()); // 0-4 分
     eprintln!("Crack time: {:?}", estimate.crack_times());
 
@@ -71,3 +361,101 @@ pub fn check_password_strength(password: &str) {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        CharClass,
+        process::process_gen_pass::{
+            PasswordPolicy, process_derive_pass, process_gen_pass, process_gen_pass_with_policy,
+        },
+    };
+
+    #[test]
+    fn test_policy_check() {
+        let policy = PasswordPolicy {
+            required_classes: vec![CharClass::Symbol],
+            banned_substrings: vec!["acme".into()],
+            ..Default::default()
+        };
+        assert!(policy.check("x7#kP2!qLm").is_ok());
+        assert!(policy.check("x7kP2qLm").is_err());
+        assert!(policy.check("ACME#2024").is_err());
+
+        let policy = PasswordPolicy {
+            pwned_db: Some("fixtures/pwned_passwords.txt".into()),
+            ..Default::default()
+        };
+        assert!(policy.check("hunter2").is_err());
+        assert!(policy.check("x7#kP2!qLm").is_ok());
+    }
+
+    #[test]
+    fn test_gen_pass_with_policy() {
+        let policy = PasswordPolicy::load("fixtures/password_policy.toml").unwrap();
+        let password = process_gen_pass_with_policy(16, true, true, true, true, &policy).unwrap();
+        assert!(policy.check(&password).is_ok());
+
+        assert!(process_gen_pass_with_policy(16, true, true, true, false, &policy).is_err());
+
+        let impossible = PasswordPolicy {
+            min_score: Some(4),
+            max_attempts: Some(3),
+            ..Default::default()
+        };
+        assert!(process_gen_pass_with_policy(4, true, false, false, false, &impossible).is_err());
+
+        let out_of_range = PasswordPolicy {
+            min_score: Some(5),
+            ..Default::default()
+        };
+        assert!(out_of_range.validated().is_err());
+        assert!(impossible.validated().is_ok());
+    }
+
+    #[test]
+    fn test_gen_pass_length_below_classes() {
+        assert!(process_gen_pass(2, true, true, true, true).is_err());
+        assert!(process_gen_pass(4, false, false, false, false).is_err());
+        assert_eq!(
+            process_gen_pass(4, true, true, true, true).unwrap().len(),
+            4
+        );
+
+        let policy = PasswordPolicy {
+            required_classes: vec![CharClass::Number, CharClass::Upper, CharClass::Number],
+            ..Default::default()
+        };
+        assert!(policy.check_length(1).is_err());
+        assert!(policy.check_length(2).is_ok());
+        assert!(process_gen_pass_with_policy(1, true, true, true, true, &policy).is_err());
+    }
+
+    #[test]
+    fn test_derive_pass_is_deterministic() {
+        let derive = |site, counter| {
+            process_derive_pass("master", site, "me", counter, 20, true, true, true, true).unwrap()
+        };
+        let password = derive("example.com", 1);
+        // Known answers, a change here breaks every password derived so far
+        assert_eq!(password, "XuxdmIuytC&@xUTtX7Dy");
+        assert_eq!(password, derive("example.com", 1));
+        assert_ne!(password, derive("example.com", 2));
+        assert_ne!(password, derive("example.org", 1));
+        assert_eq!(password.len(), 20);
+
+        let policy = PasswordPolicy {
+            required_classes: vec![
+                CharClass::Number,
+                CharClass::Lower,
+                CharClass::Upper,
+                CharClass::Symbol,
+            ],
+            ..Default::default()
+        };
+        assert!(policy.check(&password).is_ok());
+
+        let digits = process_derive_pass("master", "a", "b", 1, 6, true, false, false, false);
+        assert_eq!(digits.unwrap(), "083953");
+    }
+}
@@ -0,0 +1,305 @@
+use std::{
+    io::{self, Read, Write},
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+
+use anyhow::{Context, Result};
+use data_encoding::HEXLOWER;
+use sha2::{Digest, Sha256, Sha512};
+use sha3::Sha3_256;
+
+use crate::{HashAlgorithm, open_input};
+
+/// Hex digest of everything in `reader`
+pub fn digest_reader(mut reader: impl Read, algorithm: HashAlgorithm) -> Result<String> {
+    let digest = match algorithm {
+        HashAlgorithm::Blake3 => {
+            let mut hasher = blake3::Hasher::new();
+            io::copy(&mut reader, &mut hasher)?;
+            hasher.finalize().as_bytes().to_vec()
+        }
+        HashAlgorithm::Sha256 => digest_with::<Sha256>(reader)?,
+        HashAlgorithm::Sha512 => digest_with::<Sha512>(reader)?,
+        HashAlgorithm::Sha3_256 => digest_with::<Sha3_256>(reader)?,
+    };
+    Ok(HEXLOWER.encode(&digest))
+}
+
+fn digest_with<D: Digest + Write>(mut reader: impl Read) -> Result<Vec<u8>> {
+    let mut hasher = D::new();
+    io::copy(&mut reader, &mut hasher)?;
+    Ok(hasher.finalize().to_vec())
+}
+
+fn digest_file(file: &str, algorithm: HashAlgorithm) -> Result<String> {
+    let reader = open_input(file).with_context(|| format!("{file}: open failed"))?;
+    digest_reader(reader, algorithm).with_context(|| format!("{file}: read failed"))
+}
+
+/// Hash the files on all cores, results come back in the order of `jobs`
+fn digest_files(jobs: &[(&str, HashAlgorithm)]) -> Vec<Result<String>> {
+    let workers = thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .min(jobs.len());
+    if workers <= 1 {
+        return jobs
+            .iter()
+            .map(|(file, algorithm)| digest_file(file, *algorithm))
+            .collect();
+    }
+
+    let next = AtomicUsize::new(0);
+    let mut results = thread::scope(|scope| {
+        let handles = (0..workers)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut done = Vec::new();
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        let Some((file, algorithm)) = jobs.get(i) else {
+                            return done;
+                        };
+                        done.push((i, digest_file(file, *algorithm)));
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("hash worker panicked"))
+            .collect::<Vec<_>>()
+    });
+    results.sort_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// One checksum line, coreutils style by default or BSD style with `tag`. Names with a
+/// backslash or newline are escaped and the line starts with `\` like coreutils does.
+pub fn format_checksum(algorithm: HashAlgorithm, digest: &str, file: &str, tag: bool) -> String {
+    let escaped = file.contains(['\\', '\n', '\r']);
+    let name = match escaped {
+        true => file
+            .replace('\\', "\\\\")
+            .replace('\n', "\\n")
+            .replace('\r', "\\r"),
+        false => file.to_string(),
+    };
+    let prefix = if escaped { "\\" } else { "" };
+    match tag {
+        true => format!("{prefix}{} ({name}) = {digest}", algorithm.tag()),
+        false => format!("{prefix}{digest}  {name}"),
+    }
+}
+
+/// Parse a coreutils or BSD style line into algorithm, digest and file name
+pub fn parse_checksum(
+    line: &str,
+    algorithm: HashAlgorithm,
+) -> Option<(HashAlgorithm, String, String)> {
+    let (escaped, line) = match line.strip_prefix('\\') {
+        Some(line) => (true, line),
+        None => (false, line),
+    };
+
+    let (algorithm, digest, name) = match line.split_once(" (") {
+        Some((tag, rest)) if HashAlgorithm::from_tag(tag).is_some() => {
+            let (name, digest) = rest.rsplit_once(") = ")?;
+            (HashAlgorithm::from_tag(tag)?, digest, name)
+        }
+        _ => {
+            let (digest, name) = line.split_once(' ')?;
+            // `*` marks binary mode, which makes no difference here
+            let name = name.strip_prefix([' ', '*'])?;
+            (algorithm, digest, name)
+        }
+    };
+
+    let valid =
+        digest.len() == algorithm.hex_len() && digest.bytes().all(|b| b.is_ascii_hexdigit());
+    if !valid || name.is_empty() {
+        return None;
+    }
+    let name = match escaped {
+        true => unescape_name(name)?,
+        false => name.to_string(),
+    };
+    Some((algorithm, digest.to_ascii_lowercase(), name))
+}
+
+fn unescape_name(name: &str) -> Option<String> {
+    let mut unescaped = String::with_capacity(name.len());
+    let mut chars = name.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unescaped.push(match chars.next()? {
+                '\\' => '\\',
+                'n' => '\n',
+                'r' => '\r',
+                _ => return None,
+            }),
+            c => unescaped.push(c),
+        }
+    }
+    Some(unescaped)
+}
+
+pub fn process_hash(files: &[String], algorithm: HashAlgorithm, tag: bool) -> Result<()> {
+    let jobs = files
+        .iter()
+        .map(|file| (file.as_str(), algorithm))
+        .collect::<Vec<_>>();
+    let mut failed = 0;
+    for (file, result) in files.iter().zip(digest_files(&jobs)) {
+        match result {
+            Ok(digest) => println!("{}", format_checksum(algorithm, &digest, file, tag)),
+            Err(e) => {
+                eprintln!("{:#}", e);
+                failed += 1;
+            }
+        }
+    }
+
+    if failed > 0 {
+        return Err(anyhow::anyhow!("{failed} file(s) could not be read"));
+    }
+    Ok(())
+}
+
+/// Verify every file listed in the checksum lists, printing OK or FAILED per file
+pub fn process_hash_check(lists: &[String], algorithm: HashAlgorithm, quiet: bool) -> Result<()> {
+    let mut entries = Vec::new();
+    let mut malformed = 0;
+    for list in lists {
+        let mut content = String::new();
+        open_input(list)?
+            .read_to_string(&mut content)
+            .with_context(|| format!("Read checksum list {list} failed"))?;
+        for (i, line) in content.lines().enumerate() {
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match parse_checksum(line, algorithm) {
+                Some(entry) => entries.push(entry),
+                None => {
+                    eprintln!("{list}: {}: improperly formatted checksum line", i + 1);
+                    malformed += 1;
+                }
+            }
+        }
+    }
+    if entries.is_empty() {
+        return Err(anyhow::anyhow!(
+            "No properly formatted checksum lines found"
+        ));
+    }
+
+    let jobs = entries
+        .iter()
+        .map(|(algorithm, _, file)| (file.as_str(), *algorithm))
+        .collect::<Vec<_>>();
+    let results = digest_files(&jobs);
+
+    let (mut mismatched, mut unreadable) = (0, 0);
+    for ((_, expected, file), result) in entries.iter().zip(results) {
+        match result {
+            Ok(digest) if digest == *expected => {
+                if !quiet {
+                    println!("{file}: OK");
+                }
+            }
+            Ok(_) => {
+                println!("{file}: FAILED");
+                mismatched += 1;
+            }
+            Err(e) => {
+                eprintln!("{:#}", e);
+                println!("{file}: FAILED open or read");
+                unreadable += 1;
+            }
+        }
+    }
+
+    if malformed > 0 {
+        eprintln!("WARNING: {malformed} line(s) are improperly formatted");
+    }
+    if unreadable > 0 {
+        eprintln!("WARNING: {unreadable} listed file(s) could not be read");
+    }
+    if mismatched > 0 {
+        eprintln!("WARNING: {mismatched} computed checksum(s) did NOT match");
+    }
+    if mismatched + unreadable > 0 {
+        return Err(anyhow::anyhow!("Checksum verification failed"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        HashAlgorithm,
+        process::process_hash::{digest_files, digest_reader, format_checksum, parse_checksum},
+    };
+
+    #[test]
+    fn test_digest_known_values() {
+        for (algorithm, expected) in [
+            (
+                HashAlgorithm::Sha256,
+                "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            ),
+            (
+                HashAlgorithm::Sha3_256,
+                "3a985da74fe225b2045c172d6bd390bd855f086e3e9d525b46bfe24511431532",
+            ),
+            (
+                HashAlgorithm::Blake3,
+                "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85",
+            ),
+        ] {
+            assert_eq!(digest_reader(&b"abc"[..], algorithm).unwrap(), expected);
+        }
+        assert!(
+            digest_reader(&b"abc"[..], HashAlgorithm::Sha512)
+                .unwrap()
+                .starts_with("ddaf35a193617aba")
+        );
+
+        let results = digest_files(&[
+            ("fixtures/juventus.csv", HashAlgorithm::Sha256),
+            ("missing", HashAlgorithm::Sha256),
+            ("Cargo.toml", HashAlgorithm::Blake3),
+        ]);
+        assert!(results[0].is_ok() && results[1].is_err() && results[2].is_ok());
+    }
+
+    #[test]
+    fn test_checksum_line_round_trip() {
+        let digest = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+        for (name, tag) in [
+            ("a b.txt", false),
+            ("a b.txt", true),
+            ("odd\\name\n", false),
+        ] {
+            let line = format_checksum(HashAlgorithm::Blake3, digest, name, tag);
+            let (algorithm, parsed, file) = parse_checksum(&line, HashAlgorithm::Sha256).unwrap();
+            assert_eq!(file, name);
+            assert_eq!(parsed, digest);
+            let expected = if tag {
+                HashAlgorithm::Blake3
+            } else {
+                HashAlgorithm::Sha256
+            };
+            assert_eq!(algorithm, expected);
+        }
+
+        assert_eq!(
+            format_checksum(HashAlgorithm::Sha256, digest, "f", true),
+            format!("SHA256 (f) = {digest}")
+        );
+        assert!(parse_checksum(&format!("{digest} *bin"), HashAlgorithm::Sha256).is_some());
+        assert!(parse_checksum(&format!("{digest}  f"), HashAlgorithm::Sha512).is_none());
+        assert!(parse_checksum("not a checksum", HashAlgorithm::Sha256).is_none());
+    }
+}
@@ -0,0 +1,230 @@
+use std::io::{self, Read, Write};
+
+use anyhow::{Context, Result};
+
+use crate::{
+    open_input, open_output, read_buffer_from_input, utils::read_full, write_buffer_to_output,
+};
+
+const COLOR_PRINTABLE: &str = "\x1b[32m";
+const COLOR_WHITESPACE: &str = "\x1b[33m";
+const COLOR_CONTROL: &str = "\x1b[31m";
+const COLOR_EDGE: &str = "\x1b[37m";
+const COLOR_RESET: &str = "\x1b[0m";
+
+/// Most zero bytes a gap between dump lines may fill, the output is built in memory
+const MAX_UNDUMP_GAP: u64 = 64 * 1024 * 1024;
+
+/// Xxd layout: offset, `cols` bytes per line in groups of `group`, then the ascii column.
+/// A group of 0 puts the whole line in one group.
+pub fn hex_dump(
+    mut reader: impl Read,
+    mut writer: impl Write,
+    offset: u64,
+    cols: usize,
+    group: usize,
+    color: bool,
+) -> Result<()> {
+    if cols == 0 {
+        return Err(anyhow::anyhow!("Columns must be greater than 0"));
+    }
+    let group = if group == 0 { cols } else { group };
+    let mut line = vec![0u8; cols];
+    let mut offset = offset;
+    loop {
+        let n = read_full(&mut reader, &mut line)?;
+        if n == 0 {
+            break;
+        }
+        dump_line(&mut writer, offset, &line[..n], cols, group, color)?;
+        offset += n as u64;
+        if n < cols {
+            break;
+        }
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+fn dump_line(
+    writer: &mut impl Write,
+    offset: u64,
+    bytes: &[u8],
+    cols: usize,
+    group: usize,
+    color: bool,
+) -> io::Result<()> {
+    write!(writer, "{offset:08x}:")?;
+    let mut width = 0;
+    for (i, byte) in bytes.iter().enumerate() {
+        if i % group == 0 {
+            writer.write_all(b" ")?;
+            width += 1;
+        }
+        match color {
+            true => write!(writer, "{}{byte:02x}{COLOR_RESET}", byte_color(*byte))?,
+            false => write!(writer, "{byte:02x}")?,
+        }
+        width += 2;
+    }
+
+    let full = cols * 2 + cols.div_ceil(group);
+    write!(writer, "{:pad$}  ", "", pad = full - width)?;
+    for byte in bytes {
+        let c = match byte {
+            0x20..=0x7e => *byte as char,
+            _ => '.',
+        };
+        match color {
+            true => write!(writer, "{}{c}{COLOR_RESET}", byte_color(*byte))?,
+            false => write!(writer, "{c}")?,
+        }
+    }
+    writer.write_all(b"\n")
+}
+
+fn byte_color(byte: u8) -> &'static str {
+    match byte {
+        0x00 | 0xff => COLOR_EDGE,
+        b'\t' | b'\n' | b'\r' | b' ' => COLOR_WHITESPACE,
+        0x21..=0x7e => COLOR_PRINTABLE,
+        _ => COLOR_CONTROL,
+    }
+}
+
+/// Turn an xxd style dump back into bytes. Lines with an `offset:` prefix are placed at
+/// that offset plus `seek`, gaps up to 64 MiB are zero filled, and the ascii column after
+/// the hex is ignored. Lines without an offset are plain hex (`xxd -p`) appended to the output.
+pub fn hex_undump(dump: &str, seek: i64) -> Result<Vec<u8>> {
+    let mut result = Vec::new();
+    for (number, line) in dump.lines().enumerate() {
+        let number = number + 1;
+        let line = line.trim_end();
+        if line.is_empty() {
+            continue;
+        }
+
+        let hex = match line.split_once(':') {
+            Some((offset, rest)) => {
+                let offset = u64::from_str_radix(offset.trim(), 16)
+                    .with_context(|| format!("Invalid offset at line {number}"))?;
+                let position = offset
+                    .checked_add_signed(seek)
+                    .with_context(|| format!("Offset plus seek is negative at line {number}"))?;
+                let gap = position.checked_sub(result.len() as u64).with_context(|| {
+                    format!("Offset {offset:x} at line {number} overlaps earlier data")
+                })?;
+                if gap > MAX_UNDUMP_GAP {
+                    return Err(anyhow::anyhow!(
+                        "Offset {offset:x} at line {number} leaves a gap of {gap} bytes, \
+                         at most {MAX_UNDUMP_GAP} are zero filled"
+                    ));
+                }
+                result.resize(result.len() + gap as usize, 0);
+
+                // The hex area ends where the two spaces before the ascii column start
+                let rest = rest.strip_prefix(' ').unwrap_or(rest);
+                rest.split_once("  ").map_or(rest, |(hex, _)| hex)
+            }
+            None => line,
+        };
+
+        let digits = hex
+            .chars()
+            .filter(|c| !c.is_ascii_whitespace())
+            .collect::<Vec<_>>();
+        if digits.len() % 2 != 0 {
+            return Err(anyhow::anyhow!("Odd number of hex digits at line {number}"));
+        }
+        for pair in digits.chunks(2) {
+            let pair = pair.iter().collect::<String>();
+            let byte = u8::from_str_radix(&pair, 16)
+                .with_context(|| format!("Invalid hex {pair:?} at line {number}"))?;
+            result.push(byte);
+        }
+    }
+
+    Ok(result)
+}
+
+pub fn process_hex_dump(
+    input: &str,
+    cols: usize,
+    group: usize,
+    seek: u64,
+    length: Option<u64>,
+    color: bool,
+) -> Result<()> {
+    let mut reader = open_input(input)?;
+    let skipped = io::copy(&mut reader.by_ref().take(seek), &mut io::sink())?;
+    if skipped < seek {
+        return Err(anyhow::anyhow!("Seek {seek} is past the end of input"));
+    }
+    let reader = reader.take(length.unwrap_or(u64::MAX));
+    hex_dump(reader, open_output("-")?, seek, cols, group, color)
+}
+
+pub fn process_hex_undump(input: &str, output: &str, seek: i64) -> Result<()> {
+    let buf = read_buffer_from_input(input)?;
+    let dump = String::from_utf8(buf).context("Hex dump must be utf8 text")?;
+    write_buffer_to_output(output, &hex_undump(&dump, seek)?)
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use crate::process::process_hex::{MAX_UNDUMP_GAP, hex_dump, hex_undump};
+
+    fn dump(data: &[u8], offset: u64, cols: usize, group: usize) -> String {
+        let mut out = Vec::new();
+        hex_dump(data, &mut out, offset, cols, group, false).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_hex_dump_matches_xxd() {
+        assert_eq!(
+            dump(b"hello\n", 0, 16, 2),
+            "00000000: 6865 6c6c 6f0a                           hello.\n"
+        );
+        assert_eq!(
+            dump(b"llo world, this is l", 2, 16, 4),
+            "00000002: 6c6c6f20 776f726c 642c2074 68697320  llo world, this \n\
+             00000012: 6973206c                             is l\n"
+        );
+        assert_eq!(
+            dump(b"hello", 0, 4, 3),
+            "00000000: 68656c 6c  hell\n00000004: 6f         o\n"
+        );
+    }
+
+    #[test]
+    fn test_hex_undump_round_trip() {
+        let key = fs::read("fixtures/ed25519.sk").unwrap();
+        for (cols, group) in [(16, 2), (8, 1), (12, 0), (4, 3)] {
+            assert_eq!(hex_undump(&dump(&key, 0, cols, group), 0).unwrap(), key);
+        }
+
+        // Offsets are absolute unless shifted back by seek, like xxd -r -s
+        let shifted = dump(b"abc", 2, 16, 2);
+        assert_eq!(hex_undump(&shifted, 0).unwrap(), b"\0\0abc");
+        assert_eq!(hex_undump(&shifted, -2).unwrap(), b"abc");
+
+        assert_eq!(hex_undump("6162\n63 0a\n", 0).unwrap(), b"abc\n");
+        assert!(hex_undump("00000000: 616  a", 0).is_err());
+        assert!(hex_undump("zz", 0).is_err());
+
+        // Huge offsets would zero fill gigabytes before the data
+        assert!(hex_undump("ffffffffffff: 00  .", 0).is_err());
+        assert!(hex_undump("00000000: 00  .", 0x7fff_ffff_ffff).is_err());
+        let edge = format!("{MAX_UNDUMP_GAP:08x}: 01  .");
+        assert_eq!(
+            hex_undump(&edge, 0).unwrap().len() as u64,
+            MAX_UNDUMP_GAP + 1
+        );
+        let past = format!("{:08x}: 01  .", MAX_UNDUMP_GAP + 1);
+        assert!(hex_undump(&past, 0).is_err());
+    }
+}
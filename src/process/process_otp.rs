@@ -0,0 +1,227 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use chacha20poly1305::aead::{OsRng, rand_core::RngCore};
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use percent_encoding::{NON_ALPHANUMERIC, utf8_percent_encode};
+use sha1::Sha1;
+use sha2::{Sha256, Sha512};
+use subtle::ConstantTimeEq;
+
+use crate::OtpAlgorithm;
+
+/// Largest accepted drift, each step is one more code an attacker can hit
+pub const MAX_OTP_WINDOW: u64 = 100;
+
+/// Random secret encoded as unpadded base32, the form authenticator apps expect
+pub fn process_otp_secret(bytes: usize) -> Result<String> {
+    if bytes < 16 {
+        return Err(anyhow::anyhow!("Otp secret must be at least 16 bytes"));
+    }
+    let mut secret = vec![0u8; bytes];
+    OsRng.fill_bytes(&mut secret);
+    Ok(BASE32_NOPAD.encode(&secret))
+}
+
+/// Decode a base32 secret, tolerating lowercase, spaces and padding
+pub fn decode_otp_secret(secret: &str) -> Result<Vec<u8>> {
+    let secret = secret
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '=' && *c != '-')
+        .collect::<String>()
+        .to_uppercase();
+    BASE32_NOPAD
+        .decode(secret.as_bytes())
+        .context("Otp secret is not valid base32")
+}
+
+/// RFC 4226 code for the given counter
+pub fn hotp(secret: &[u8], counter: u64, digits: u32, algorithm: OtpAlgorithm) -> Result<String> {
+    let counter = counter.to_be_bytes();
+    let hash = match algorithm {
+        OtpAlgorithm::Sha1 => hmac_digest::<Hmac<Sha1>>(secret, &counter)?,
+        OtpAlgorithm::Sha256 => hmac_digest::<Hmac<Sha256>>(secret, &counter)?,
+        OtpAlgorithm::Sha512 => hmac_digest::<Hmac<Sha512>>(secret, &counter)?,
+    };
+
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes(hash[offset..offset + 4].try_into()?) & 0x7fff_ffff;
+    let code = binary as u64 % 10u64.pow(digits);
+    Ok(format!("{:0width$}", code, width = digits as usize))
+}
+
+fn hmac_digest<M: Mac + hmac::digest::KeyInit>(key: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+    let mut mac = <M as hmac::digest::KeyInit>::new_from_slice(key)
+        .map_err(|e| anyhow::anyhow!(e.to_string()))?;
+    mac.update(data);
+    Ok(mac.finalize().into_bytes().to_vec())
+}
+
+fn now() -> Result<u64> {
+    Ok(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs())
+}
+
+/// HOTP code when a counter is given, otherwise the current RFC 6238 TOTP code
+pub fn process_otp_code(
+    secret: &str,
+    counter: Option<u64>,
+    digits: u32,
+    period: u64,
+    algorithm: OtpAlgorithm,
+) -> Result<String> {
+    let secret = decode_otp_secret(secret)?;
+    let counter = match counter {
+        Some(counter) => counter,
+        None => now()? / period,
+    };
+    hotp(&secret, counter, digits, algorithm)
+}
+
+/// Return the matched step offset, HOTP looks ahead and TOTP looks both ways
+pub fn process_otp_verify(
+    secret: &str,
+    code: &str,
+    counter: Option<u64>,
+    window: u64,
+    digits: u32,
+    period: u64,
+    algorithm: OtpAlgorithm,
+) -> Result<Option<i64>> {
+    if window > MAX_OTP_WINDOW {
+        return Err(anyhow::anyhow!(
+            "Window must be at most {MAX_OTP_WINDOW} steps"
+        ));
+    }
+    let secret = decode_otp_secret(secret)?;
+    let window = window as i64;
+    let (base, offsets) = match counter {
+        Some(counter) => (counter, 0..=window),
+        None => (now()? / period, -window..=window),
+    };
+
+    for offset in offsets {
+        let Some(step) = base.checked_add_signed(offset) else {
+            continue;
+        };
+        let expected = hotp(&secret, step, digits, algorithm)?;
+        if bool::from(expected.as_bytes().ct_eq(code.as_bytes())) {
+            return Ok(Some(offset));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Provisioning uri in the Key Uri Format understood by authenticator apps
+#[allow(clippy::too_many_arguments)]
+pub fn process_otp_uri(
+    secret: &str,
+    account: &str,
+    issuer: Option<&str>,
+    counter: Option<u64>,
+    digits: u32,
+    period: u64,
+    algorithm: OtpAlgorithm,
+) -> Result<String> {
+    // Normalize and validate the secret before publishing it in the uri
+    let secret = BASE32_NOPAD.encode(&decode_otp_secret(secret)?);
+    let kind = if counter.is_some() { "hotp" } else { "totp" };
+    let account = utf8_percent_encode(account, NON_ALPHANUMERIC);
+    let label = match issuer {
+        Some(issuer) => format!(
+            "{}:{}",
+            utf8_percent_encode(issuer, NON_ALPHANUMERIC),
+            account
+        ),
+        None => account.to_string(),
+    };
+
+    let mut uri = format!(
+        "otpauth://{}/{}?secret={}&algorithm={}&digits={}",
+        kind,
+        label,
+        secret,
+        algorithm.to_string().to_uppercase(),
+        digits
+    );
+    match counter {
+        Some(counter) => uri.push_str(&format!("&counter={}", counter)),
+        None => uri.push_str(&format!("&period={}", period)),
+    }
+    if let Some(issuer) = issuer {
+        uri.push_str(&format!(
+            "&issuer={}",
+            utf8_percent_encode(issuer, NON_ALPHANUMERIC)
+        ));
+    }
+
+    Ok(uri)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        OtpAlgorithm,
+        process::process_otp::{decode_otp_secret, hotp, process_otp_uri, process_otp_verify},
+    };
+
+    #[test]
+    fn test_hotp_rfc4226_vectors() {
+        let secret = b"12345678901234567890";
+        let expected = ["755224", "287082", "359152", "969429", "338314"];
+        for (counter, code) in expected.iter().enumerate() {
+            assert_eq!(
+                hotp(secret, counter as u64, 6, OtpAlgorithm::Sha1).unwrap(),
+                *code
+            );
+        }
+    }
+
+    #[test]
+    fn test_totp_rfc6238_vectors() {
+        let step = 59 / 30;
+        let sha1 = b"12345678901234567890";
+        let sha256 = b"12345678901234567890123456789012";
+        let sha512 = b"1234567890123456789012345678901234567890123456789012345678901234";
+        assert_eq!(hotp(sha1, step, 8, OtpAlgorithm::Sha1).unwrap(), "94287082");
+        assert_eq!(
+            hotp(sha256, step, 8, OtpAlgorithm::Sha256).unwrap(),
+            "46119246"
+        );
+        assert_eq!(
+            hotp(sha512, step, 8, OtpAlgorithm::Sha512).unwrap(),
+            "90693936"
+        );
+    }
+
+    #[test]
+    fn test_otp_verify_and_uri() {
+        let secret = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+        assert_eq!(
+            decode_otp_secret("gezd gnbv gy3t qojq gezd gnbv gy3t qojq").unwrap(),
+            b"12345678901234567890"
+        );
+        let verify = |code| process_otp_verify(secret, code, Some(2), 2, 6, 30, OtpAlgorithm::Sha1);
+        assert_eq!(verify("969429").unwrap(), Some(1));
+        assert_eq!(verify("755224").unwrap(), None);
+        assert_eq!(verify("96942").unwrap(), None);
+        let far = process_otp_verify(secret, "969429", Some(2), 101, 6, 30, OtpAlgorithm::Sha1);
+        assert!(far.is_err());
+
+        let uri = process_otp_uri(
+            secret,
+            "me@acme.com",
+            Some("Acme Co"),
+            None,
+            6,
+            30,
+            OtpAlgorithm::Sha1,
+        )
+        .unwrap();
+        assert_eq!(
+            uri,
+            "otpauth://totp/Acme%20Co:me%40acme%2Ecom?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&algorithm=SHA1&digits=6&period=30&issuer=Acme%20Co"
+        );
+    }
+}
@@ -0,0 +1,301 @@
+use anyhow::{Context, Result};
+use argon2::{
+    Algorithm, Argon2, Version,
+    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+};
+use chacha20poly1305::aead::OsRng;
+use csv::ReaderBuilder;
+use scrypt::Scrypt;
+use zxcvbn::zxcvbn;
+
+use crate::{PasswordHashAlgorithm, PwnedDb, read_buffer_from_input};
+
+const MAX_SCORE: u8 = 4;
+
+#[derive(Debug)]
+pub struct PasswordReport {
+    pub password: String,
+    pub score: u8,
+    pub crack_time: String,
+    pub warning: Option<String>,
+    pub suggestions: Vec<String>,
+}
+
+pub fn audit_password(password: &str, user_inputs: &[&str]) -> PasswordReport {
+    let estimate = zxcvbn(password, user_inputs);
+    let (warning, suggestions) = match estimate.feedback() {
+        Some(feedback) => (
+            feedback.warning().map(|w| w.to_string()),
+            feedback
+                .suggestions()
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+        ),
+        None => (None, vec![]),
+    };
+
+    PasswordReport {
+        password: password.to_string(),
+        score: estimate.score().into(),
+        crack_time: estimate
+            .crack_times()
+            .offline_slow_hashing_1e4_per_second()
+            .to_string(),
+        warning,
+        suggestions,
+    }
+}
+
+/// Read passwords one per line, or from a named column when the input is csv
+pub fn read_passwords(input: &str, column: Option<&str>, delimiter: char) -> Result<Vec<String>> {
+    let buf = read_buffer_from_input(input)?;
+    let Some(column) = column else {
+        let content = String::from_utf8(buf).context("Passwords must be utf8 text")?;
+        return Ok(content
+            .lines()
+            .map(|line| line.trim_end_matches('\r'))
+            .filter(|line| !line.is_empty())
+            .map(String::from)
+            .collect());
+    };
+
+    let mut rdr = ReaderBuilder::new()
+        .delimiter(delimiter as u8)
+        .from_reader(buf.as_slice());
+    let index = rdr
+        .headers()?
+        .iter()
+        .position(|header| header == column)
+        .with_context(|| format!("Column {column} not found in csv headers"))?;
+
+    let mut passwords = Vec::new();
+    for record in rdr.records() {
+        let record = record?;
+        if let Some(password) = record.get(index).filter(|p| !p.is_empty()) {
+            passwords.push(password.to_string());
+        }
+    }
+
+    Ok(passwords)
+}
+
+pub fn process_password_audit(
+    input: &str,
+    column: Option<&str>,
+    delimiter: char,
+    user_inputs: &[String],
+    min_score: u8,
+    pwned_db: Option<&str>,
+    show: bool,
+) -> Result<()> {
+    let passwords = read_passwords(input, column, delimiter)?;
+    let mut pwned_db = pwned_db.map(PwnedDb::open).transpose()?;
+    let user_inputs = user_inputs.iter().map(String::as_str).collect::<Vec<_>>();
+    let mut distribution = [0usize; MAX_SCORE as usize + 1];
+    let mut weak = 0;
+
+    for (i, password) in passwords.iter().enumerate() {
+        let report = audit_password(password, &user_inputs);
+        let breached = match pwned_db.as_mut() {
+            Some(db) => db.lookup(password)?,
+            None => 0,
+        };
+        distribution[report.score as usize] += 1;
+        let status = if report.score < min_score || breached > 0 {
+            weak += 1;
+            "FAIL"
+        } else {
+            "OK"
+        };
+
+        match show {
+            true => println!("#{} {}: {}", i + 1, status, report.password),
+            false => println!("#{} {}", i + 1, status),
+        }
+        println!("  Score: {}/{}", report.score, MAX_SCORE);
+        println!("  Crack time: {}", report.crack_time);
+        if breached > 0 {
+            println!("  Breached: seen {} times", breached);
+        }
+        if let Some(warning) = &report.warning {
+            println!("  Warning: {}", warning);
+        }
+        for suggestion in &report.suggestions {
+            println!("  Suggestion: {}", suggestion);
+        }
+    }
+
+    println!("Score distribution:");
+    for (score, count) in distribution.iter().enumerate() {
+        println!("  {}: {}", score, count);
+    }
+    println!("Total: {}, weak or breached: {}", passwords.len(), weak);
+
+    if weak > 0 {
+        return Err(anyhow::anyhow!(
+            "{weak} password(s) scored below {min_score} or were breached"
+        ));
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct HashParams {
+    /// Argon2 memory cost in KiB
+    pub memory: u32,
+    /// Argon2 iterations
+    pub iterations: u32,
+    /// Argon2 lanes or scrypt parallelism
+    pub parallelism: u32,
+    /// Scrypt log2 of the cpu/memory cost
+    pub log_n: u8,
+    /// Scrypt block size
+    pub block_size: u32,
+    /// Bcrypt cost
+    pub cost: u32,
+}
+
+impl Default for HashParams {
+    // Argon2id and scrypt defaults follow OWASP password storage recommendations
+    fn default() -> Self {
+        Self {
+            memory: 19456,
+            iterations: 2,
+            parallelism: 1,
+            log_n: 17,
+            block_size: 8,
+            cost: 12,
+        }
+    }
+}
+
+/// Argon2id and scrypt produce PHC strings, bcrypt produces its own `$2b$` format
+pub fn hash_password(
+    password: &[u8],
+    algorithm: PasswordHashAlgorithm,
+    params: &HashParams,
+) -> Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = match algorithm {
+        PasswordHashAlgorithm::Argon2id => {
+            let argon2_params =
+                argon2::Params::new(params.memory, params.iterations, params.parallelism, None)
+                    .map_err(|e| anyhow::anyhow!("Invalid argon2 params: {}", e))?;
+            Argon2::new(Algorithm::Argon2id, Version::V0x13, argon2_params)
+                .hash_password(password, &salt)
+                .map_err(|e| anyhow::anyhow!("Argon2 hash failed: {}", e))?
+                .to_string()
+        }
+        PasswordHashAlgorithm::Scrypt => {
+            let scrypt_params = scrypt::Params::new(
+                params.log_n,
+                params.block_size,
+                params.parallelism,
+                scrypt::Params::RECOMMENDED_LEN,
+            )
+            .map_err(|e| anyhow::anyhow!("Invalid scrypt params: {}", e))?;
+            Scrypt
+                .hash_password_customized(password, None, None, scrypt_params, &salt)
+                .map_err(|e| anyhow::anyhow!("Scrypt hash failed: {}", e))?
+                .to_string()
+        }
+        PasswordHashAlgorithm::Bcrypt => {
+            bcrypt::non_truncating_hash(password, params.cost).context("Bcrypt hash failed")?
+        }
+    };
+
+    Ok(hash)
+}
+
+/// Pick the algorithm from the hash prefix and check the password against it
+pub fn verify_password(password: &[u8], hash: &str) -> Result<bool> {
+    let hash = hash.trim();
+    if ["$2a$", "$2b$", "$2x$", "$2y$"]
+        .iter()
+        .any(|prefix| hash.starts_with(prefix))
+    {
+        return bcrypt::verify(password, hash).context("Bcrypt verify failed");
+    }
+
+    let parsed =
+        PasswordHash::new(hash).map_err(|e| anyhow::anyhow!("Invalid PHC string: {}", e))?;
+    let result = match parsed.algorithm.as_str() {
+        "argon2id" | "argon2i" | "argon2d" => Argon2::default().verify_password(password, &parsed),
+        "scrypt" => Scrypt.verify_password(password, &parsed),
+        algorithm => return Err(anyhow::anyhow!("Unsupported hash algorithm {}", algorithm)),
+    };
+
+    match result {
+        Ok(()) => Ok(true),
+        Err(argon2::password_hash::Error::Password) => Ok(false),
+        Err(e) => Err(anyhow::anyhow!("Verify password failed: {}", e)),
+    }
+}
+
+pub fn process_password_hash(
+    input: &str,
+    algorithm: PasswordHashAlgorithm,
+    params: &HashParams,
+) -> Result<String> {
+    let password = read_buffer_from_input(input)?;
+    hash_password(&password, algorithm, params)
+}
+
+pub fn process_password_verify(input: &str, hash: &str) -> Result<bool> {
+    let password = read_buffer_from_input(input)?;
+    verify_password(&password, hash)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        PasswordHashAlgorithm,
+        process::process_password::{
+            HashParams, audit_password, hash_password, read_passwords, verify_password,
+        },
+    };
+
+    #[test]
+    fn test_audit_password_with_user_inputs() {
+        // A name is strong on its own but weak once it is known to be the user's
+        let report = audit_password("mariorossi1987", &[]);
+        let with_context = audit_password("mariorossi1987", &["mariorossi"]);
+        assert!(report.score >= 3);
+        assert!(with_context.score < report.score);
+        assert!(audit_password("password", &[]).score < 2);
+    }
+
+    #[test]
+    fn test_read_passwords_from_csv_column() {
+        let passwords = read_passwords("fixtures/juventus.csv", Some("Name"), ',').unwrap();
+        assert!(!passwords.is_empty());
+        assert!(read_passwords("fixtures/juventus.csv", Some("Missing"), ',').is_err());
+    }
+
+    #[test]
+    fn test_hash_and_verify_password() {
+        // Cheap params to keep the test fast
+        let params = HashParams {
+            memory: 1024,
+            iterations: 1,
+            log_n: 4,
+            cost: 4,
+            ..Default::default()
+        };
+        for algorithm in [
+            PasswordHashAlgorithm::Argon2id,
+            PasswordHashAlgorithm::Scrypt,
+            PasswordHashAlgorithm::Bcrypt,
+        ] {
+            let hash = hash_password(b"correct horse", algorithm, &params).unwrap();
+            assert!(verify_password(b"correct horse", &hash).unwrap());
+            assert!(!verify_password(b"wrong horse", &hash).unwrap());
+        }
+
+        let hash = hash_password(b"pw", PasswordHashAlgorithm::Argon2id, &params).unwrap();
+        assert!(hash.starts_with("$argon2id$v=19$m=1024,t=1,p=1$"));
+        assert!(verify_password(b"pw", "$md5$abc").is_err());
+    }
+}
@@ -0,0 +1,101 @@
+use std::{
+    cmp::Ordering,
+    fs::File,
+    io::{BufRead, BufReader, Seek, SeekFrom},
+};
+
+use anyhow::{Context, Result};
+use sha1::{Digest, Sha1};
+
+const SHA1_HEX_LEN: usize = 40;
+
+/// Local Pwned Passwords SHA-1 dump, lines of `HASH:COUNT` sorted by hash.
+/// Lookups binary search the file by byte offset, so the dump is never loaded into memory.
+pub struct PwnedDb {
+    reader: BufReader<File>,
+    len: u64,
+}
+
+impl PwnedDb {
+    pub fn open(path: &str) -> Result<Self> {
+        let file = File::open(path).with_context(|| format!("Open pwned db {path} failed"))?;
+        let len = file.metadata()?.len();
+        Ok(Self {
+            reader: BufReader::new(file),
+            len,
+        })
+    }
+
+    /// How many times the password appears in the dump, 0 when not found
+    pub fn lookup(&mut self, password: &str) -> Result<u64> {
+        let hash = format!("{:X}", Sha1::digest(password.as_bytes()));
+        self.lookup_hash(&hash)
+    }
+
+    pub fn lookup_hash(&mut self, hash: &str) -> Result<u64> {
+        let target = hash.to_ascii_uppercase();
+        let (mut lo, mut hi) = (0, self.len);
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            let Some((start, line)) = self.line_from(mid)? else {
+                hi = mid;
+                continue;
+            };
+            let (line_hash, count) = parse_line(&line)?;
+            match target.as_str().cmp(line_hash.as_str()) {
+                Ordering::Equal => return Ok(count),
+                Ordering::Less => hi = mid,
+                Ordering::Greater => lo = start + 1,
+            }
+        }
+
+        Ok(0)
+    }
+
+    /// First complete line starting at or after `pos`, with its start offset
+    fn line_from(&mut self, pos: u64) -> Result<Option<(u64, String)>> {
+        let mut start = pos;
+        let mut line = String::new();
+        if pos > 0 {
+            self.reader.seek(SeekFrom::Start(pos - 1))?;
+            start = pos - 1 + self.reader.read_line(&mut line)? as u64;
+            line.clear();
+        } else {
+            self.reader.seek(SeekFrom::Start(0))?;
+        }
+        if start >= self.len {
+            return Ok(None);
+        }
+
+        self.reader.read_line(&mut line)?;
+        Ok(Some((start, line)))
+    }
+}
+
+fn parse_line(line: &str) -> Result<(String, u64)> {
+    let line = line.trim_end();
+    let (hash, count) = line.split_once(':').unwrap_or((line, "1"));
+    if hash.len() != SHA1_HEX_LEN {
+        return Err(anyhow::anyhow!("Invalid pwned db line: {}", line));
+    }
+    let count = count
+        .parse()
+        .with_context(|| format!("Invalid pwned db count: {}", line))?;
+    Ok((hash.to_ascii_uppercase(), count))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::process::process_pwned::PwnedDb;
+
+    #[test]
+    fn test_pwned_db_lookup() {
+        let mut db = PwnedDb::open("fixtures/pwned_passwords.txt").unwrap();
+        assert_eq!(db.lookup("password").unwrap(), 10434004);
+        assert_eq!(db.lookup("juventus").unwrap(), 20132);
+        assert_eq!(db.lookup("123456").unwrap(), 42071085);
+        assert_eq!(db.lookup("x7#kP2!qLm-not-breached").unwrap(), 0);
+        assert_eq!(db.lookup_hash(&"0".repeat(40)).unwrap(), 0);
+        assert_eq!(db.lookup_hash(&"F".repeat(40)).unwrap(), 0);
+    }
+}
@@ -0,0 +1,530 @@
+use std::{
+    fs,
+    io::{self, Cursor, Read},
+    path::Path,
+    time::SystemTime,
+};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use super::process_text::{
+    Blake3, Ed25519Signer, Ed25519Verifier, KeyFingerprint, KeyLoad, StreamSign, StreamVerify,
+    TextSign, TextVerify,
+};
+use crate::{
+    Ed25519Key, SignatureFormat, TextSignFormat, open_input_trimmed, read_buffer_from_input,
+};
+
+const ENVELOPE_VERSION: u8 = 1;
+const ARMOR_BEGIN: &str = "-----BEGIN RCLI SIGNATURE-----";
+const ARMOR_END: &str = "-----END RCLI SIGNATURE-----";
+
+/// Detached signature with the metadata needed to verify it. The signature covers the data
+/// followed by every other field, so the key id, time, file name and size can't be changed
+/// without invalidating it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SignatureEnvelope {
+    pub version: u8,
+    pub algorithm: String,
+    pub key_id: String,
+    pub created: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    /// Ed25519ph context string
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<String>,
+    pub size: u64,
+    pub signature: String,
+}
+
+/// The envelope fields the signature covers, all but the signature itself
+#[derive(Serialize)]
+struct SignedFields<'a> {
+    version: u8,
+    algorithm: &'a str,
+    key_id: &'a str,
+    created: &'a str,
+    file: Option<&'a str>,
+    context: Option<&'a str>,
+    size: u64,
+}
+
+impl SignatureEnvelope {
+    /// Appended to the data before signing, the signed fields as compact json
+    fn signed_trailer(&self) -> Result<Vec<u8>> {
+        let fields = SignedFields {
+            version: self.version,
+            algorithm: &self.algorithm,
+            key_id: &self.key_id,
+            created: &self.created,
+            file: self.file.as_deref(),
+            context: self.context.as_deref(),
+            size: self.size,
+        };
+        Ok(serde_json::to_vec(&fields)?)
+    }
+
+    pub fn render(&self, format: SignatureFormat) -> Result<String> {
+        match format {
+            SignatureFormat::Json => Ok(serde_json::to_string_pretty(self)? + "\n"),
+            SignatureFormat::Armor => {
+                let headers = [&self.file, &self.context];
+                if headers
+                    .iter()
+                    .any(|h| h.as_ref().is_some_and(|h| h.contains('\n')))
+                {
+                    return Err(anyhow::anyhow!(
+                        "File names and contexts with newlines can't be armored, use json"
+                    ));
+                }
+                let mut armor = format!(
+                    "{ARMOR_BEGIN}\nVersion: {}\nAlgorithm: {}\nKey-Id: {}\nCreated: {}\n",
+                    self.version, self.algorithm, self.key_id, self.created
+                );
+                if let Some(file) = &self.file {
+                    armor.push_str(&format!("File: {file}\n"));
+                }
+                if let Some(context) = &self.context {
+                    armor.push_str(&format!("Context: {context}\n"));
+                }
+                armor.push_str(&format!(
+                    "Size: {}\n\n{}\n{ARMOR_END}\n",
+                    self.size, self.signature
+                ));
+                Ok(armor)
+            }
+        }
+    }
+
+    /// Accept either the json or the armored form
+    pub fn parse(content: &str) -> Result<Self> {
+        let content = content.trim();
+        if content.starts_with('{') {
+            let envelope: Self =
+                serde_json::from_str(content).context("Invalid json signature file")?;
+            return envelope.checked();
+        }
+
+        let body = content
+            .strip_prefix(ARMOR_BEGIN)
+            .and_then(|body| body.strip_suffix(ARMOR_END))
+            .context("Signature file is neither json nor an armored signature")?;
+        let (headers, signature) = body
+            .trim()
+            .split_once("\n\n")
+            .context("Armored signature is missing the blank line before the signature")?;
+
+        let mut envelope = Self {
+            version: 0,
+            algorithm: String::new(),
+            key_id: String::new(),
+            created: String::new(),
+            file: None,
+            context: None,
+            size: 0,
+            signature: signature.split_whitespace().collect(),
+        };
+        for line in headers.lines() {
+            let (name, value) = line
+                .split_once(": ")
+                .with_context(|| format!("Invalid armor header: {line}"))?;
+            let value = value.trim().to_string();
+            match name {
+                "Version" => envelope.version = value.parse().context("Invalid version")?,
+                "Algorithm" => envelope.algorithm = value,
+                "Key-Id" => envelope.key_id = value,
+                "Created" => envelope.created = value,
+                "File" => envelope.file = Some(value),
+                "Context" => envelope.context = Some(value),
+                "Size" => envelope.size = value.parse().context("Invalid size")?,
+                name => return Err(anyhow::anyhow!("Unknown armor header: {name}")),
+            }
+        }
+        envelope.checked()
+    }
+
+    fn checked(self) -> Result<Self> {
+        if self.version != ENVELOPE_VERSION {
+            return Err(anyhow::anyhow!(
+                "Unsupported signature version {}",
+                self.version
+            ));
+        }
+        if self.algorithm.is_empty() || self.key_id.is_empty() || self.signature.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Signature file must have an algorithm, key id and signature"
+            ));
+        }
+        if self.context.is_some() && self.algorithm != TextSignFormat::ED25519ph.to_string() {
+            return Err(anyhow::anyhow!(
+                "A context string is only supported for ed25519ph"
+            ));
+        }
+        Ok(self)
+    }
+
+    pub fn format(&self) -> Result<TextSignFormat> {
+        match self.algorithm.parse()? {
+            format @ (TextSignFormat::Blake3
+            | TextSignFormat::ED25519
+            | TextSignFormat::ED25519ph) => Ok(format),
+            format => Err(anyhow::anyhow!("{format} can't be used for signatures")),
+        }
+    }
+}
+
+/// The signed message, the data followed by the envelope's signed fields. The size is
+/// counted while the data is read, so the trailer is only built after it.
+struct SignedMessage<R> {
+    data: R,
+    envelope: SignatureEnvelope,
+    trailer: Option<Cursor<Vec<u8>>>,
+}
+
+impl<R: Read> SignedMessage<R> {
+    fn new(data: R, envelope: SignatureEnvelope) -> Self {
+        Self {
+            data,
+            envelope: SignatureEnvelope {
+                size: 0,
+                ..envelope
+            },
+            trailer: None,
+        }
+    }
+}
+
+impl<R: Read> Read for SignedMessage<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.trailer.is_none() {
+            let n = self.data.read(buf)?;
+            if n > 0 || buf.is_empty() {
+                self.envelope.size += n as u64;
+                return Ok(n);
+            }
+            let trailer = self.envelope.signed_trailer().map_err(io::Error::other)?;
+            self.trailer = Some(Cursor::new(trailer));
+        }
+        self.trailer
+            .as_mut()
+            .map_or(Ok(0), |trailer| trailer.read(buf))
+    }
+}
+
+/// Plain ed25519 signs the whole message at once, so it is read into memory first
+struct Buffered<T>(T);
+
+impl<T: TextSign> StreamSign for Buffered<T> {
+    fn sign_reader(&self, reader: &mut dyn Read) -> Result<String> {
+        let mut message = Vec::new();
+        reader.read_to_end(&mut message)?;
+        self.0.sign(&message)
+    }
+}
+
+impl<T: TextVerify> StreamVerify for Buffered<T> {
+    fn verify_reader(&self, reader: &mut dyn Read, sign: String) -> Result<bool> {
+        let mut message = Vec::new();
+        reader.read_to_end(&mut message)?;
+        self.0.verify(&message, sign)
+    }
+}
+
+/// Sign everything `reader` yields, only plain ed25519 holds it in memory
+pub fn sign_envelope(
+    reader: impl Read,
+    file: Option<String>,
+    key: &str,
+    format: TextSignFormat,
+    context: Option<&str>,
+) -> Result<SignatureEnvelope> {
+    let (key_id, signer): (_, Box<dyn StreamSign>) = match (format, context) {
+        (TextSignFormat::Blake3, None) => {
+            let signer = Blake3::load(key)?;
+            (signer.fingerprint(), Box::new(signer))
+        }
+        (TextSignFormat::ED25519, None) => {
+            let signer = Ed25519Signer::load(key)?;
+            (signer.fingerprint(), Box::new(Buffered(signer)))
+        }
+        (TextSignFormat::ED25519ph, context) => {
+            let signer = Ed25519Signer::load(key)?.prehashed(context.map(str::as_bytes));
+            (signer.fingerprint(), Box::new(signer))
+        }
+        (TextSignFormat::Blake3 | TextSignFormat::ED25519, Some(_)) => {
+            return Err(anyhow::anyhow!(
+                "A context string is only supported for ed25519ph"
+            ));
+        }
+        _ => return Err(anyhow::anyhow!("Invalid text sign format")),
+    };
+
+    let envelope = SignatureEnvelope {
+        version: ENVELOPE_VERSION,
+        algorithm: format.to_string(),
+        key_id,
+        created: humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+        file,
+        context: context.map(str::to_string),
+        size: 0,
+        signature: String::new(),
+    };
+    let mut message = SignedMessage::new(reader, envelope);
+    let signature = signer.sign_reader(&mut message)?;
+    Ok(SignatureEnvelope {
+        signature,
+        ..message.envelope
+    })
+}
+
+/// Check the envelope against everything `reader` yields, the key must have the
+/// envelope's key id
+pub fn verify_envelope(reader: impl Read, envelope: &SignatureEnvelope, key: &str) -> Result<bool> {
+    let context = envelope.context.as_deref().map(str::as_bytes);
+    let verifier: Box<dyn StreamVerify> = match envelope.format()? {
+        TextSignFormat::Blake3 => {
+            let verifier = Blake3::load(key)?;
+            if verifier.fingerprint() != envelope.key_id {
+                return Err(anyhow::anyhow!(
+                    "Key {key} doesn't match key id {}",
+                    envelope.key_id
+                ));
+            }
+            Box::new(verifier)
+        }
+        TextSignFormat::ED25519ph => {
+            Box::new(load_ed25519_verifier(key, &envelope.key_id)?.prehashed(context))
+        }
+        _ => Box::new(Buffered(load_ed25519_verifier(key, &envelope.key_id)?)),
+    };
+
+    let mut message = SignedMessage::new(reader, envelope.clone());
+    let valid = verifier.verify_reader(&mut message, envelope.signature.clone())?;
+    Ok(valid && message.envelope.size == envelope.size)
+}
+
+/// A raw ed25519 key file is 32 bytes either way, so try it as the public key and as
+/// the secret key it derives from, and keep the reading with the wanted key id. Framed
+/// formats like PEM or OpenSSH say which one they are.
+fn load_ed25519_verifier(key: &str, key_id: &str) -> Result<Ed25519Verifier> {
+    let bytes = read_buffer_from_input(key)?;
+    let mut candidates = Vec::new();
+    match Ed25519Key::decode(&bytes)? {
+        (Ed25519Key::Raw(raw), _) => {
+            if let Ok(public) = Ed25519Verifier::from_public_bytes(&raw) {
+                candidates.push(public);
+            }
+            candidates.push(Ed25519Signer::new(raw).verifier());
+        }
+        _ => candidates.push(Ed25519Verifier::decode(&bytes)?),
+    }
+    candidates
+        .into_iter()
+        .find(|verifier| verifier.fingerprint() == key_id)
+        .with_context(|| format!("Key {key} doesn't match key id {key_id}"))
+}
+
+/// Look through `dir` for a key file whose fingerprint matches the envelope
+pub fn find_signature_key(dir: &str, envelope: &SignatureEnvelope) -> Result<String> {
+    let format = envelope.format()?;
+    let mut entries = fs::read_dir(dir)
+        .with_context(|| format!("Read key dir {dir} failed"))?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.is_file())
+        .collect::<Vec<_>>();
+    entries.sort();
+
+    for path in entries {
+        let Some(path) = path.to_str() else {
+            continue;
+        };
+        let found = match format {
+            TextSignFormat::Blake3 => {
+                Blake3::load(path).is_ok_and(|key| key.fingerprint() == envelope.key_id)
+            }
+            _ => load_ed25519_verifier(path, &envelope.key_id).is_ok(),
+        };
+        if found {
+            return Ok(path.to_string());
+        }
+    }
+
+    Err(anyhow::anyhow!(
+        "No {} key with id {} found in {dir}",
+        envelope.algorithm,
+        envelope.key_id
+    ))
+}
+
+/// Stdin loses its trailing newline, as for signatures without a signature file
+pub fn process_text_sign_detached(
+    input: &str,
+    key: &str,
+    format: TextSignFormat,
+    context: Option<&str>,
+    sig_format: SignatureFormat,
+) -> Result<String> {
+    let file = match input {
+        "-" => None,
+        input => Path::new(input)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned()),
+    };
+    let reader = open_input_trimmed(input)?;
+    sign_envelope(reader, file, key, format, context)?.render(sig_format)
+}
+
+/// Verify with the given key, or the key in `key_dir` whose id matches the signature.
+/// A `context` given here must be the one the signature file was made with.
+pub fn process_text_verify_detached(
+    input: &str,
+    sig: &str,
+    key: Option<&str>,
+    key_dir: Option<&str>,
+    context: Option<&str>,
+) -> Result<bool> {
+    let content =
+        fs::read_to_string(sig).with_context(|| format!("Read signature {sig} failed"))?;
+    let envelope = SignatureEnvelope::parse(&content)?;
+    if context.is_some() && context != envelope.context.as_deref() {
+        return Err(anyhow::anyhow!(
+            "Context doesn't match the signature file's {:?}",
+            envelope.context.as_deref().unwrap_or_default()
+        ));
+    }
+    let key = match (key, key_dir) {
+        (Some(key), _) => key.to_string(),
+        (None, Some(key_dir)) => find_signature_key(key_dir, &envelope)?,
+        (None, None) => {
+            return Err(anyhow::anyhow!(
+                "A key or a key dir is needed to verify a signature file"
+            ));
+        }
+    };
+    verify_envelope(open_input_trimmed(input)?, &envelope, &key)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        SignatureFormat, TextSignFormat,
+        process::process_signature::{
+            SignatureEnvelope, find_signature_key, sign_envelope, verify_envelope,
+        },
+    };
+
+    #[test]
+    fn test_signature_envelope_round_trip() {
+        let data = b"release tarball";
+        for (format, key, public) in [
+            (
+                TextSignFormat::Blake3,
+                "fixtures/blake3.txt",
+                "fixtures/blake3.txt",
+            ),
+            (
+                TextSignFormat::ED25519,
+                "fixtures/ed25519.sk",
+                "fixtures/ed25519.pk",
+            ),
+            (
+                TextSignFormat::ED25519ph,
+                "fixtures/ed25519.sk",
+                "fixtures/ed25519.pk",
+            ),
+        ] {
+            let file = Some("app.tar.gz".into());
+            let envelope = sign_envelope(&data[..], file, key, format, None).unwrap();
+            for sig_format in [SignatureFormat::Json, SignatureFormat::Armor] {
+                let content = envelope.render(sig_format).unwrap();
+                let parsed = SignatureEnvelope::parse(&content).unwrap();
+                assert_eq!(parsed, envelope);
+                assert!(verify_envelope(&data[..], &parsed, public).unwrap());
+                assert!(!verify_envelope(&b"tampered"[..], &parsed, public).unwrap());
+            }
+            assert_eq!(find_signature_key("fixtures", &envelope).unwrap(), public);
+        }
+    }
+
+    #[test]
+    fn test_signature_envelope_rejects_wrong_key() {
+        let envelope = sign_envelope(
+            &b"data"[..],
+            None,
+            "fixtures/ed25519.sk",
+            TextSignFormat::ED25519,
+            None,
+        )
+        .unwrap();
+        assert!(verify_envelope(&b"data"[..], &envelope, "fixtures/ed25519.sk").unwrap());
+        let mut other = envelope;
+        other.key_id = "AAAAAAAAAAAAAAAAAAAAAA".into();
+        assert!(verify_envelope(&b"data"[..], &other, "fixtures/ed25519.pk").is_err());
+        assert!(find_signature_key("fixtures", &other).is_err());
+        assert!(SignatureEnvelope::parse("{\"version\": 1}").is_err());
+    }
+
+    #[test]
+    fn test_signature_envelope_covers_metadata() {
+        for (format, key) in [
+            (TextSignFormat::Blake3, "fixtures/blake3.txt"),
+            (TextSignFormat::ED25519, "fixtures/ed25519.sk"),
+            (TextSignFormat::ED25519ph, "fixtures/ed25519.sk"),
+        ] {
+            let envelope =
+                sign_envelope(&b"data"[..], Some("a.txt".into()), key, format, None).unwrap();
+            let tampered = [
+                SignatureEnvelope {
+                    created: "2000-01-01T00:00:00Z".into(),
+                    ..envelope.clone()
+                },
+                SignatureEnvelope {
+                    file: Some("b.txt".into()),
+                    ..envelope.clone()
+                },
+                SignatureEnvelope {
+                    file: None,
+                    ..envelope.clone()
+                },
+                SignatureEnvelope {
+                    size: 5,
+                    ..envelope.clone()
+                },
+            ];
+            for envelope in tampered {
+                assert!(
+                    !verify_envelope(&b"data"[..], &envelope, key).unwrap(),
+                    "{format}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_signature_envelope_context() {
+        let data = vec![9u8; 100_000];
+        let (key, public) = ("fixtures/ed25519.sk", "fixtures/ed25519.pk");
+        let sign = |format, context| sign_envelope(&data[..], None, key, format, context);
+        let envelope = sign(TextSignFormat::ED25519ph, Some("release")).unwrap();
+        assert_eq!(envelope.size, data.len() as u64);
+        assert_eq!(envelope.context.as_deref(), Some("release"));
+
+        let armored = envelope.render(SignatureFormat::Armor).unwrap();
+        assert!(armored.contains("Context: release\n"));
+        let parsed = SignatureEnvelope::parse(&armored).unwrap();
+        assert!(verify_envelope(&data[..], &parsed, public).unwrap());
+
+        // The context is signed too, and only ed25519ph takes one
+        let other = SignatureEnvelope {
+            context: Some("debug".into()),
+            ..parsed
+        };
+        assert!(!verify_envelope(&data[..], &other, public).unwrap());
+        assert!(sign(TextSignFormat::ED25519, Some("release")).is_err());
+        let json = other.render(SignatureFormat::Json).unwrap();
+        let json = json.replace("ed25519ph", "ed25519");
+        assert!(SignatureEnvelope::parse(&json).is_err());
+    }
+}
@@ -1,16 +1,26 @@
-use crate::{TextSignFormat, process_gen_pass, read_buffer_from_input};
+use super::{process_aead::new_cipher, process_age::generate_x25519, process_key::Ed25519Key};
+use crate::{TextSignFormat, open_input_trimmed, process_gen_pass, read_buffer_from_input};
+use aes_gcm::Aes256Gcm;
+use aes_gcm_siv::Aes256GcmSiv;
 use anyhow::Result;
 use base64::{Engine, prelude::BASE64_URL_SAFE_NO_PAD};
 use chacha20poly1305::{
-    ChaCha20Poly1305, Nonce,
-    aead::{Aead, AeadCore, KeyInit, OsRng},
+    ChaCha20Poly1305, Nonce, XChaCha20Poly1305,
+    aead::{Aead, KeyInit, OsRng},
 };
 use ed25519_dalek::{SECRET_KEY_LENGTH, Signature, Signer, SigningKey, Verifier, VerifyingKey};
 use jwt_simple::prelude::Ed25519KeyPair;
-use std::str::FromStr;
+use sha2::{Digest, Sha512};
+use std::{
+    io::{self, Read},
+    str::FromStr,
+};
+use subtle::ConstantTimeEq;
 
 type SecretKey = [u8; SECRET_KEY_LENGTH];
 const CHACHA_NONCE_LEN: usize = 12;
+const FINGERPRINT_LEN: usize = 16;
+const BLAKE3_FINGERPRINT_CONTEXT: &str = "rcli 2025-01 blake3 key fingerprint v1";
 
 pub trait TextSign {
     fn sign(&self, data: &[u8]) -> Result<String>;
@@ -20,6 +30,15 @@ pub trait TextVerify {
     fn verify(&self, data: &[u8], sign: String) -> Result<bool>;
 }
 
+/// Sign without holding the whole input in memory
+pub trait StreamSign {
+    fn sign_reader(&self, reader: &mut dyn Read) -> Result<String>;
+}
+
+pub trait StreamVerify {
+    fn verify_reader(&self, reader: &mut dyn Read, sign: String) -> Result<bool>;
+}
+
 pub trait KeyLoad {
     fn new(key: SecretKey) -> Self;
 
@@ -37,12 +56,20 @@ pub trait KeyLoad {
         Ok(Self::new(key))
     }
 
+    /// Parse the content of a key file, raw bytes unless the key type knows other formats
+    fn decode(key: &[u8]) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        Self::try_new(key)
+    }
+
     fn load(key: impl AsRef<str>) -> Result<Self>
     where
         Self: Sized,
     {
         let key = read_buffer_from_input(key.as_ref())?;
-        Self::try_new(&key)
+        Self::decode(&key)
     }
 }
 
@@ -50,6 +77,16 @@ pub trait KeyGenerator {
     fn generate() -> Result<Vec<Vec<u8>>>;
 }
 
+/// Stable key id, a signer and its verifier share the same fingerprint
+pub trait KeyFingerprint {
+    fn fingerprint(&self) -> String;
+}
+
+/// Base64 of a truncated blake3 hash of the public key
+pub fn public_key_fingerprint(public_key: &[u8]) -> String {
+    BASE64_URL_SAFE_NO_PAD.encode(&blake3::hash(public_key).as_bytes()[..FINGERPRINT_LEN])
+}
+
 pub struct Blake3 {
     key: SecretKey,
 }
@@ -62,6 +99,17 @@ pub struct Ed25519Verifier {
     key: VerifyingKey,
 }
 
+/// Ed25519ph signs a SHA-512 prehash of the message, with an optional context string
+pub struct Ed25519phSigner {
+    key: SigningKey,
+    context: Option<Vec<u8>>,
+}
+
+pub struct Ed25519phVerifier {
+    key: VerifyingKey,
+    context: Option<Vec<u8>>,
+}
+
 impl KeyLoad for Blake3 {
     fn new(key: SecretKey) -> Self {
         Self { key }
@@ -73,6 +121,12 @@ impl KeyLoad for Ed25519Signer {
         let signing_key = SigningKey::from_bytes(&key);
         Self { key: signing_key }
     }
+
+    // PKCS#8, OpenSSH, hex and base64 keys as well as raw bytes
+    fn decode(key: &[u8]) -> Result<Self> {
+        let (key, _) = Ed25519Key::decode(key)?;
+        Ok(Self { key: key.secret()? })
+    }
 }
 
 impl KeyLoad for Ed25519Verifier {
@@ -80,6 +134,70 @@ impl KeyLoad for Ed25519Verifier {
         let verifying_key = VerifyingKey::from_bytes(&key).expect("Parse public key failed");
         Self { key: verifying_key }
     }
+
+    // SPKI and OpenSSH public keys, or the public half of any secret key format
+    fn decode(key: &[u8]) -> Result<Self> {
+        let (key, _) = Ed25519Key::decode(key)?;
+        Ok(Self { key: key.public()? })
+    }
+}
+
+impl Ed25519Signer {
+    pub fn verifier(&self) -> Ed25519Verifier {
+        Ed25519Verifier {
+            key: self.key.verifying_key(),
+        }
+    }
+
+    pub fn prehashed(self, context: Option<&[u8]>) -> Ed25519phSigner {
+        Ed25519phSigner {
+            key: self.key,
+            context: context.map(<[u8]>::to_vec),
+        }
+    }
+}
+
+impl Ed25519Verifier {
+    /// Unlike `KeyLoad::new` this rejects bytes that aren't a valid curve point
+    pub fn from_public_bytes(key: &[u8]) -> Result<Self> {
+        let key: SecretKey = key.try_into()?;
+        Ok(Self {
+            key: VerifyingKey::from_bytes(&key)?,
+        })
+    }
+
+    pub fn prehashed(self, context: Option<&[u8]>) -> Ed25519phVerifier {
+        Ed25519phVerifier {
+            key: self.key,
+            context: context.map(<[u8]>::to_vec),
+        }
+    }
+}
+
+impl KeyFingerprint for Blake3 {
+    // A shared key has no public half, derive the id so it doesn't reveal the key
+    fn fingerprint(&self) -> String {
+        let id = blake3::derive_key(BLAKE3_FINGERPRINT_CONTEXT, &self.key);
+        BASE64_URL_SAFE_NO_PAD.encode(&id[..FINGERPRINT_LEN])
+    }
+}
+
+impl KeyFingerprint for Ed25519Signer {
+    fn fingerprint(&self) -> String {
+        public_key_fingerprint(self.key.verifying_key().as_bytes())
+    }
+}
+
+impl KeyFingerprint for Ed25519Verifier {
+    fn fingerprint(&self) -> String {
+        public_key_fingerprint(self.key.as_bytes())
+    }
+}
+
+impl KeyFingerprint for Ed25519phSigner {
+    fn fingerprint(&self) -> String {
+        public_key_fingerprint(self.key.verifying_key().as_bytes())
+    }
 }
 
 impl TextSign for Blake3 {
@@ -94,7 +212,25 @@ impl TextSign for Blake3 {
 impl TextVerify for Blake3 {
     fn verify(&self, data: &[u8], signed: String) -> Result<bool> {
         let cp_signed = self.sign(data)?;
-        Ok(cp_signed == signed)
+        Ok(cp_signed.as_bytes().ct_eq(signed.as_bytes()).into())
+    }
+}
+
+impl StreamSign for Blake3 {
+    // Same result as `TextSign::sign` over the whole input
+    fn sign_reader(&self, reader: &mut dyn Read) -> Result<String> {
+        let mut hasher = blake3::Hasher::new_keyed(&self.key);
+        io::copy(reader, &mut hasher)?;
+        let signed = hasher.finalize().to_string();
+        Ok(BASE64_URL_SAFE_NO_PAD.encode(signed))
+    }
+}
+
+impl StreamVerify for Blake3 {
+    fn verify_reader(&self, reader: &mut dyn Read, signed: String) -> Result<bool> {
+        // Constant time, so timing doesn't leak how much of a forged tag is right
+        let expected = self.sign_reader(reader)?;
+        Ok(expected.as_bytes().ct_eq(signed.as_bytes()).into())
     }
 }
 
@@ -116,11 +252,49 @@ impl TextVerify for Ed25519Verifier {
     }
 }
 
+impl StreamSign for Ed25519phSigner {
+    fn sign_reader(&self, reader: &mut dyn Read) -> Result<String> {
+        let mut prehash = Sha512::new();
+        io::copy(reader, &mut prehash)?;
+        let signed = self
+            .key
+            .sign_prehashed(prehash, self.context.as_deref())?
+            .to_string();
+        Ok(BASE64_URL_SAFE_NO_PAD.encode(signed))
+    }
+}
+
+impl TextSign for Ed25519phSigner {
+    fn sign(&self, data: &[u8]) -> Result<String> {
+        self.sign_reader(&mut &data[..])
+    }
+}
+
+impl StreamVerify for Ed25519phVerifier {
+    fn verify_reader(&self, reader: &mut dyn Read, sign: String) -> Result<bool> {
+        let signed = BASE64_URL_SAFE_NO_PAD.decode(sign)?;
+        let signed = Signature::from_str(str::from_utf8(&signed)?)?;
+        let mut prehash = Sha512::new();
+        io::copy(reader, &mut prehash)?;
+        Ok(self
+            .key
+            .verify_prehashed(prehash, self.context.as_deref(), &signed)
+            .is_ok())
+    }
+}
+
+impl TextVerify for Ed25519phVerifier {
+    fn verify(&self, data: &[u8], sign: String) -> Result<bool> {
+        self.verify_reader(&mut &data[..], sign)
+    }
+}
+
 pub fn process_text_sign(input: &str, key: &str, format: TextSignFormat) -> anyhow::Result<String> {
     let buf = read_buffer_from_input(input)?;
     match format {
         TextSignFormat::Blake3 => Blake3::load(key)?.sign(&buf),
         TextSignFormat::ED25519 => Ed25519Signer::load(key)?.sign(&buf),
+        TextSignFormat::ED25519ph => Ed25519Signer::load(key)?.prehashed(None).sign(&buf),
         _ => Err(anyhow::anyhow!("Invalid text sign format")),
     }
 }
@@ -153,14 +327,80 @@ pub fn process_text_verify(
     match format {
         TextSignFormat::Blake3 => Blake3::load(key)?.verify(&buf, sign),
         TextSignFormat::ED25519 => Ed25519Verifier::load(key)?.verify(&buf, sign),
+        TextSignFormat::ED25519ph => Ed25519Verifier::load(key)?
+            .prehashed(None)
+            .verify(&buf, sign),
         _ => Err(anyhow::anyhow!("Invalid text verify format")),
     }
 }
 
+/// Build a streaming signer, plain ed25519 needs the whole message so it can't stream
+pub fn stream_signer(
+    key: &str,
+    format: TextSignFormat,
+    context: Option<&[u8]>,
+) -> Result<Box<dyn StreamSign>> {
+    match (format, context) {
+        (TextSignFormat::Blake3, None) => Ok(Box::new(Blake3::load(key)?)),
+        (TextSignFormat::ED25519ph, context) => {
+            Ok(Box::new(Ed25519Signer::load(key)?.prehashed(context)))
+        }
+        (TextSignFormat::ED25519, _) => Err(anyhow::anyhow!(
+            "Ed25519 can't stream, use ed25519ph for large inputs"
+        )),
+        (TextSignFormat::Blake3, Some(_)) => Err(anyhow::anyhow!(
+            "A context string is only supported for ed25519ph"
+        )),
+        _ => Err(anyhow::anyhow!("Invalid text sign format")),
+    }
+}
+
+pub fn stream_verifier(
+    key: &str,
+    format: TextSignFormat,
+    context: Option<&[u8]>,
+) -> Result<Box<dyn StreamVerify>> {
+    match (format, context) {
+        (TextSignFormat::Blake3, None) => Ok(Box::new(Blake3::load(key)?)),
+        (TextSignFormat::ED25519ph, context) => {
+            Ok(Box::new(Ed25519Verifier::load(key)?.prehashed(context)))
+        }
+        (TextSignFormat::ED25519, _) => Err(anyhow::anyhow!(
+            "Ed25519 can't stream, use ed25519ph for large inputs"
+        )),
+        (TextSignFormat::Blake3, Some(_)) => Err(anyhow::anyhow!(
+            "A context string is only supported for ed25519ph"
+        )),
+        _ => Err(anyhow::anyhow!("Invalid text verify format")),
+    }
+}
+
+/// Sign input in chunks, stdin loses its trailing newline as in `process_text_sign`
+pub fn process_text_sign_stream(
+    input: &str,
+    key: &str,
+    format: TextSignFormat,
+    context: Option<&str>,
+) -> Result<String> {
+    let signer = stream_signer(key, format, context.map(str::as_bytes))?;
+    signer.sign_reader(&mut open_input_trimmed(input)?)
+}
+
+pub fn process_text_verify_stream(
+    input: &str,
+    key: &str,
+    format: TextSignFormat,
+    context: Option<&str>,
+    sign: String,
+) -> Result<bool> {
+    let verifier = stream_verifier(key, format, context.map(str::as_bytes))?;
+    verifier.verify_reader(&mut open_input_trimmed(input)?, sign)
+}
+
 pub fn process_key_generate(format: TextSignFormat) -> Result<Vec<Vec<u8>>> {
     match format {
         TextSignFormat::Blake3 => Blake3::generate(),
-        TextSignFormat::ED25519 => Ed25519Signer::generate(),
+        TextSignFormat::ED25519 | TextSignFormat::ED25519ph => Ed25519Signer::generate(),
         TextSignFormat::JWTED25519 => {
             let key_pair = Ed25519KeyPair::generate();
             let sk = key_pair.to_bytes();
@@ -172,42 +412,40 @@ pub fn process_key_generate(format: TextSignFormat) -> Result<Vec<Vec<u8>>> {
             let key = ChaCha20Poly1305::generate_key(&mut OsRng).to_vec();
             Ok(vec![key])
         }
+        TextSignFormat::XChaCha20Poly1305 => {
+            let key = XChaCha20Poly1305::generate_key(&mut OsRng).to_vec();
+            Ok(vec![key])
+        }
+        TextSignFormat::Aes256Gcm => Ok(vec![Aes256Gcm::generate_key(&mut OsRng).to_vec()]),
+        TextSignFormat::Aes256GcmSiv => Ok(vec![Aes256GcmSiv::generate_key(&mut OsRng).to_vec()]),
+        TextSignFormat::X25519 => Ok(generate_x25519()),
     }
 }
 
-pub fn process_text_encrypt(input: &str, key: &str) -> Result<Vec<u8>> {
-    let data = read_buffer_from_input(input)?;
-    let key = read_buffer_from_input(key)?;
-    let cipher = ChaCha20Poly1305::new_from_slice(&key).unwrap();
-    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
-    let ciphertext = cipher.encrypt(&nonce, data.as_ref()).unwrap();
-    let mut result = nonce.to_vec();
-    result.extend_from_slice(&ciphertext);
-
-    Ok(result)
-}
-
-pub fn process_text_decrypt(input: &str, key: &str) -> Result<Vec<u8>> {
+/// Decrypt the raw `nonce || ciphertext` layout written before the container format
+pub fn process_text_decrypt_legacy(input: &str, key: &str) -> Result<Vec<u8>> {
     let data = read_buffer_from_input(input)?;
     if data.len() < CHACHA_NONCE_LEN {
         return Err(anyhow::anyhow!("Invalid ciphertext: too short"));
     }
     let key = read_buffer_from_input(key)?;
-    let (nonce_bytes, ciphertext) = data.split_at(12);
+    let (nonce_bytes, ciphertext) = data.split_at(CHACHA_NONCE_LEN);
     let nonce = Nonce::from_slice(nonce_bytes);
-    let cipher =
-        ChaCha20Poly1305::new_from_slice(&key).map_err(|e| anyhow::anyhow!(e.to_string()))?;
-    let ciphertext = cipher
+    let cipher = new_cipher::<ChaCha20Poly1305>(&key)?;
+    let plaintext = cipher
         .decrypt(nonce, ciphertext.as_ref())
         .map_err(|e| anyhow::anyhow!(e.to_string()))?;
 
-    Ok(ciphertext)
+    Ok(plaintext)
 }
 
 #[cfg(test)]
 mod test {
+    use std::fs;
+
     use crate::process::process_text::{
-        Blake3, Ed25519Signer, Ed25519Verifier, KeyGenerator, KeyLoad, TextSign, TextVerify,
+        Blake3, Ed25519Signer, Ed25519Verifier, KeyFingerprint, KeyGenerator, KeyLoad, StreamSign,
+        StreamVerify, TextSign, TextVerify,
     };
 
     #[test]
@@ -219,6 +457,64 @@ mod test {
         assert!(blake3.verify(data, signed).is_ok());
     }
 
+    #[test]
+    fn test_key_fingerprint() {
+        let key = Ed25519Signer::generate().unwrap();
+        let signer = Ed25519Signer::try_new(&key[0]).unwrap();
+        let verifier = Ed25519Verifier::try_new(&key[1]).unwrap();
+        assert_eq!(signer.fingerprint(), verifier.fingerprint());
+        assert_eq!(signer.fingerprint().len(), 22);
+
+        // Key ids are stored in signature files, so they must never change
+        let blake3 = Blake3::load("fixtures/blake3.txt").unwrap();
+        assert_eq!(blake3.fingerprint(), "2-iYnXcAr0TC72r4tnrCVg");
+        let ed25519 = Ed25519Signer::load("fixtures/ed25519.sk").unwrap();
+        assert_eq!(ed25519.fingerprint(), "c9_-R1swEfsq3nwoZ3zrvA");
+        let public = Ed25519Verifier::load("fixtures/ed25519.pk").unwrap();
+        assert_eq!(public.fingerprint(), "c9_-R1swEfsq3nwoZ3zrvA");
+    }
+
+    #[test]
+    fn test_stream_sign_matches_and_verifies() {
+        let data = fs::read("fixtures/juventus.csv").unwrap();
+        let blake3 = Blake3::load("fixtures/blake3.txt").unwrap();
+        let signed = blake3.sign_reader(&mut &data[..]).unwrap();
+        assert_eq!(signed, blake3.sign(&data).unwrap());
+        assert!(
+            blake3
+                .verify_reader(&mut &data[..], signed.clone())
+                .unwrap()
+        );
+        let mut forged = signed.into_bytes();
+        forged[0] ^= 1;
+        let forged = String::from_utf8(forged).unwrap();
+        assert!(
+            !blake3
+                .verify_reader(&mut &data[..], forged.clone())
+                .unwrap()
+        );
+        assert!(!blake3.verify(&data, forged).unwrap());
+
+        let signer = Ed25519Signer::load("fixtures/ed25519.sk").unwrap();
+        let verifier = Ed25519Verifier::load("fixtures/ed25519.pk").unwrap();
+        let plain = signer.sign(&data).unwrap();
+        let signer = signer.prehashed(Some(b"release"));
+        let signed = signer.sign_reader(&mut &data[..]).unwrap();
+        assert_ne!(signed, plain);
+
+        let verifier = verifier.prehashed(Some(b"release"));
+        assert!(
+            verifier
+                .verify_reader(&mut &data[..], signed.clone())
+                .unwrap()
+        );
+        assert!(!verifier.verify(b"other", signed.clone()).unwrap());
+        let wrong_context = Ed25519Verifier::load("fixtures/ed25519.pk")
+            .unwrap()
+            .prehashed(None);
+        assert!(!wrong_context.verify(&data, signed).unwrap());
+    }
+
     #[test]
     fn test_ed25519_sign_and_verify() {
         let key = Ed25519Signer::generate().unwrap();
@@ -0,0 +1,128 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use base64::{Engine, prelude::BASE64_URL_SAFE_NO_PAD};
+use chacha20poly1305::aead::{OsRng, rand_core::RngCore};
+use data_encoding::{BASE32_NOPAD, HEXLOWER};
+use ulid::Ulid;
+use uuid::Builder;
+
+use crate::TokenFormat;
+
+const CHECKSUM_LEN: usize = 4;
+
+/// Random token from the same OsRng used for key generation in `process_text`
+pub fn generate_token(bytes: usize, format: TokenFormat) -> Result<String> {
+    let token = match format {
+        TokenFormat::Hex => HEXLOWER.encode(&random_bytes(bytes)?),
+        TokenFormat::Base32 => BASE32_NOPAD.encode(&random_bytes(bytes)?),
+        TokenFormat::Base64Url => BASE64_URL_SAFE_NO_PAD.encode(random_bytes(bytes)?),
+        TokenFormat::Base58 => bs58::encode(random_bytes(bytes)?).into_string(),
+        TokenFormat::UuidV4 => {
+            let mut random = [0u8; 16];
+            OsRng.fill_bytes(&mut random);
+            Builder::from_random_bytes(random).into_uuid().to_string()
+        }
+        TokenFormat::UuidV7 => {
+            let mut random = [0u8; 10];
+            OsRng.fill_bytes(&mut random);
+            Builder::from_unix_timestamp_millis(now_millis()?, &random)
+                .into_uuid()
+                .to_string()
+        }
+        TokenFormat::Ulid => {
+            let mut random = [0u8; 16];
+            OsRng.fill_bytes(&mut random);
+            Ulid::from_parts(now_millis()?, u128::from_be_bytes(random)).to_string()
+        }
+    };
+
+    Ok(token)
+}
+
+fn random_bytes(bytes: usize) -> Result<Vec<u8>> {
+    if bytes == 0 {
+        return Err(anyhow::anyhow!("Token bytes must be greater than 0"));
+    }
+    let mut buf = vec![0u8; bytes];
+    OsRng.fill_bytes(&mut buf);
+    Ok(buf)
+}
+
+fn now_millis() -> Result<u64> {
+    Ok(SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64)
+}
+
+/// Short blake3 checksum over prefix and body, lets scanners reject typos offline
+pub fn token_checksum(token: &str) -> String {
+    let hash = blake3::hash(token.as_bytes());
+    HEXLOWER.encode(&hash.as_bytes()[..CHECKSUM_LEN])
+}
+
+pub fn verify_token_checksum(token: &str) -> bool {
+    let split = token.len().saturating_sub(CHECKSUM_LEN * 2);
+    match (token.get(..split), token.get(split..)) {
+        (Some(body), Some(checksum)) if !body.is_empty() => token_checksum(body) == checksum,
+        _ => false,
+    }
+}
+
+pub fn process_token(
+    bytes: usize,
+    format: TokenFormat,
+    prefix: &str,
+    checksum: bool,
+    count: usize,
+) -> Result<Vec<String>> {
+    (0..count)
+        .map(|_| {
+            let mut token = format!("{}{}", prefix, generate_token(bytes, format)?);
+            if checksum {
+                token.push_str(&token_checksum(&token));
+            }
+            Ok(token)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use uuid::Uuid;
+
+    use crate::{
+        TokenFormat,
+        process::process_token::{generate_token, process_token, verify_token_checksum},
+    };
+
+    #[test]
+    fn test_generate_token_formats() {
+        assert_eq!(generate_token(16, TokenFormat::Hex).unwrap().len(), 32);
+        assert_eq!(generate_token(20, TokenFormat::Base32).unwrap().len(), 32);
+        assert_eq!(
+            generate_token(30, TokenFormat::Base64Url).unwrap().len(),
+            40
+        );
+        assert!(generate_token(0, TokenFormat::Hex).is_err());
+
+        let v4 = Uuid::parse_str(&generate_token(0, TokenFormat::UuidV4).unwrap()).unwrap();
+        assert_eq!(v4.get_version_num(), 4);
+        let v7 = Uuid::parse_str(&generate_token(0, TokenFormat::UuidV7).unwrap()).unwrap();
+        assert_eq!(v7.get_version_num(), 7);
+        assert_eq!(generate_token(0, TokenFormat::Ulid).unwrap().len(), 26);
+    }
+
+    #[test]
+    fn test_token_prefix_and_checksum() {
+        let tokens = process_token(24, TokenFormat::Base58, "sk_live_", true, 3).unwrap();
+        assert_eq!(tokens.len(), 3);
+        for token in tokens {
+            assert!(token.starts_with("sk_live_"));
+            assert!(verify_token_checksum(&token));
+            let mut tampered = token.into_bytes();
+            tampered[9] = if tampered[9] == b'a' { b'b' } else { b'a' };
+            assert!(!verify_token_checksum(
+                &String::from_utf8(tampered).unwrap()
+            ));
+        }
+    }
+}
@@ -1,14 +1,148 @@
-use std::{fs::File, io::Read};
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Chain, Cursor, IsTerminal, Read, Write},
+    path::Path,
+};
 
 use anyhow::Context;
 
-pub fn read_buffer_from_input(input: &str) -> anyhow::Result<Vec<u8>> {
-    let is_stdin = input == "-";
-    let mut reader: Box<dyn Read> = if is_stdin {
+/// Open a file for streaming, or stdin when input is `-`
+pub fn open_input(input: &str) -> anyhow::Result<Box<dyn Read>> {
+    Ok(if input == "-" {
         Box::new(std::io::stdin())
     } else {
         Box::new(File::open(input).with_context(|| format!("Open file: {input} failed"))?)
-    };
+    })
+}
+
+/// Like `open_input`, but drop a final newline from stdin the way `read_buffer_from_input`
+/// does, so streamed and buffered reads of the same input agree
+pub fn open_input_trimmed(input: &str) -> anyhow::Result<Box<dyn Read>> {
+    let reader = open_input(input)?;
+    Ok(match input {
+        "-" => Box::new(TrimFinalNewline::new(reader)),
+        _ => reader,
+    })
+}
+
+/// Hold a trailing newline back until more data shows it isn't the last byte
+pub(crate) struct TrimFinalNewline<R> {
+    inner: R,
+    pending: Vec<u8>,
+}
+
+impl<R> TrimFinalNewline<R> {
+    pub(crate) fn new(inner: R) -> Self {
+        Self {
+            inner,
+            pending: Vec::new(),
+        }
+    }
+}
+
+impl<R: Read> Read for TrimFinalNewline<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            let ready = self.pending.len() - usize::from(self.pending.ends_with(b"\n"));
+            if ready > 0 {
+                let n = ready.min(buf.len());
+                buf[..n].copy_from_slice(&self.pending[..n]);
+                self.pending.drain(..n);
+                return Ok(n);
+            }
+
+            let mut chunk = [0u8; 8192];
+            let n = match self.inner.read(&mut chunk) {
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            if n == 0 {
+                return Ok(0);
+            }
+            self.pending.extend_from_slice(&chunk[..n]);
+        }
+    }
+}
+
+/// Create a buffered file for streaming, or stdout when output is `-`. Flush it when done,
+/// dropping it unflushed loses write errors
+pub fn open_output(output: &str) -> anyhow::Result<Box<dyn Write>> {
+    Ok(if output == "-" {
+        Box::new(BufWriter::new(std::io::stdout()))
+    } else {
+        let file = File::create(output).with_context(|| format!("Create file: {output} failed"))?;
+        Box::new(BufWriter::new(file))
+    })
+}
+
+/// Run `write` against a temporary file next to `output` and move it into place only when
+/// it succeeds, so a failure leaves neither a partial file nor a clobbered old one. Stdout
+/// can't be taken back, it is written directly.
+pub fn write_output_atomic(
+    output: &str,
+    write: impl FnOnce(Box<dyn Write>) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    if output == "-" {
+        return write(open_output(output)?);
+    }
+
+    let path = Path::new(output);
+    let name = path
+        .file_name()
+        .with_context(|| format!("Output {output} is not a file name"))?;
+    let tmp = path.with_file_name(format!(
+        ".{}.{}.tmp",
+        name.to_string_lossy(),
+        std::process::id()
+    ));
+    let result = File::create(&tmp)
+        .with_context(|| format!("Create file: {} failed", tmp.display()))
+        .and_then(|file| write(Box::new(BufWriter::new(file))));
+    match result {
+        Ok(()) => fs::rename(&tmp, path).with_context(|| format!("Write file: {output} failed")),
+        Err(e) => {
+            let _ = fs::remove_file(&tmp);
+            Err(e)
+        }
+    }
+}
+
+/// Fill `buf` unless the reader hits EOF first, chunked codecs need aligned reads
+pub(crate) fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+/// The bytes `read_head` consumed followed by the rest of the reader
+pub(crate) type HeadReader<R> = Chain<Cursor<Vec<u8>>, R>;
+
+/// Read the first `len` bytes to detect a format, fewer at EOF, and return them with a
+/// reader that still starts at the beginning. One read from a pipe may return less.
+pub(crate) fn read_head<R: Read>(
+    mut reader: R,
+    len: usize,
+) -> io::Result<(Vec<u8>, HeadReader<R>)> {
+    let mut head = vec![0u8; len];
+    let n = read_full(&mut reader, &mut head)?;
+    head.truncate(n);
+    Ok((head.clone(), Cursor::new(head).chain(reader)))
+}
+
+pub fn read_buffer_from_input(input: &str) -> anyhow::Result<Vec<u8>> {
+    let is_stdin = input == "-";
+    let mut reader = open_input(input)?;
     let mut buf = Vec::new();
     reader.read_to_end(&mut buf)?;
 
@@ -18,3 +152,128 @@ pub fn read_buffer_from_input(input: &str) -> anyhow::Result<Vec<u8>> {
     }
     Ok(buf)
 }
+
+/// Write raw bytes to a file, or to stdout when output is `-`
+pub fn write_buffer_to_output(output: &str, data: &[u8]) -> anyhow::Result<()> {
+    if output != "-" {
+        return std::fs::write(output, data)
+            .with_context(|| format!("Write file: {output} failed"));
+    }
+
+    let mut stdout = std::io::stdout().lock();
+    if stdout.is_terminal() && std::str::from_utf8(data).is_err() {
+        eprintln!(
+            "WARNING: Output is binary, writing it to the terminal may mess it up, use --output instead"
+        );
+    }
+    stdout.write_all(data)?;
+    stdout.flush()?;
+    Ok(())
+}
+
+/// Read a secret from the env var if given, otherwise prompt on the terminal without echo
+pub fn read_secret(prompt: &str, env: Option<&str>) -> anyhow::Result<String> {
+    let secret = match env {
+        Some(name) => std::env::var(name).with_context(|| format!("Read env var {name} failed"))?,
+        None => rpassword::prompt_password(prompt).context("Read secret from terminal failed")?,
+    };
+    if secret.is_empty() {
+        return Err(anyhow::anyhow!("Secret must not be empty"));
+    }
+    Ok(secret)
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use std::io::{self, Read, Write};
+
+    use crate::{
+        utils::{TrimFinalNewline, read_head, write_output_atomic},
+        write_buffer_to_output,
+    };
+
+    /// Return one byte per read, like a slow pipe
+    pub(crate) struct Trickle<'a>(pub &'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let Some((first, rest)) = self.0.split_first() else {
+                return Ok(0);
+            };
+            match buf.first_mut() {
+                Some(b) => *b = *first,
+                None => return Ok(0),
+            }
+            self.0 = rest;
+            Ok(1)
+        }
+    }
+
+    #[test]
+    fn test_write_output_atomic() {
+        let path = std::env::temp_dir().join(format!("rcli-atomic-{}.bin", std::process::id()));
+        let output = path.to_str().unwrap();
+        write_output_atomic(output, |mut writer| Ok(writer.write_all(b"old")?)).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"old");
+
+        // A failure after some output keeps the old file and leaves no temporary behind
+        let result = write_output_atomic(output, |mut writer| {
+            writer.write_all(b"partial")?;
+            writer.flush()?;
+            Err(anyhow::anyhow!("authentication failed"))
+        });
+        assert!(result.is_err());
+        assert_eq!(std::fs::read(&path).unwrap(), b"old");
+        let dir = std::fs::read_dir(std::env::temp_dir()).unwrap();
+        let leftover = format!(".rcli-atomic-{}.bin.", std::process::id());
+        assert!(
+            !dir.flatten()
+                .any(|e| e.file_name().to_string_lossy().starts_with(&leftover))
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_trim_final_newline() {
+        for (input, trimmed) in [
+            (&b"abc\n"[..], &b"abc"[..]),
+            (b"abc", b"abc"),
+            (b"a\n\nb\n\n", b"a\n\nb\n"),
+            (b"\n", b""),
+            (b"", b""),
+        ] {
+            let mut reader = TrimFinalNewline::new(Trickle(input));
+            let mut out = Vec::new();
+            reader.read_to_end(&mut out).unwrap();
+            assert_eq!(out, trimmed);
+        }
+    }
+
+    #[test]
+    fn test_read_head() {
+        let (head, mut reader) = read_head(Trickle(b"magic bytes"), 5).unwrap();
+        assert_eq!(head, b"magic");
+        let mut all = Vec::new();
+        reader.read_to_end(&mut all).unwrap();
+        assert_eq!(all, b"magic bytes");
+
+        let (head, _) = read_head(Trickle(b"ab"), 5).unwrap();
+        assert_eq!(head, b"ab");
+    }
+
+    #[test]
+    fn test_write_buffer_to_output_file() {
+        let path = std::env::temp_dir().join(format!("rcli-utils-{}.bin", std::process::id()));
+        let output = path.to_str().unwrap();
+        let data = [0xffu8, 0x00, 0xfe, b'\n'];
+        write_buffer_to_output(output, &data).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), data);
+
+        // Writing again replaces the file instead of appending
+        write_buffer_to_output(output, b"ok").unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"ok");
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(write_buffer_to_output("missing-dir/out.bin", b"ok").is_err());
+    }
+}